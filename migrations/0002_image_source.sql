@@ -0,0 +1,3 @@
+-- migrations/0002_image_source.sql
+ALTER TABLE images ADD COLUMN original_filename TEXT;
+ALTER TABLE images ADD COLUMN checksum TEXT;
@@ -0,0 +1,2 @@
+-- migrations/0003_album_downloads.sql
+ALTER TABLE albums ADD COLUMN allow_download BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- migrations/0004_album_visibility.sql
+ALTER TABLE albums ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public'
+    CHECK (visibility IN ('public', 'unlisted', 'private', 'password'));
+ALTER TABLE albums ADD COLUMN password_hash TEXT;
@@ -0,0 +1,15 @@
+-- migrations/0005_share_links.sql
+CREATE TABLE share_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    album_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT,
+    allow_download BOOLEAN NOT NULL DEFAULT 0,
+    max_views INTEGER,
+    view_count INTEGER NOT NULL DEFAULT 0,
+    revoked BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (album_id) REFERENCES albums(id)
+);
+
+CREATE INDEX idx_share_links_album_id ON share_links(album_id);
@@ -0,0 +1,14 @@
+-- migrations/0007_album_permissions.sql
+-- Albums created before ownership existed have no owner and are managed by site owners
+ALTER TABLE albums ADD COLUMN owner_id INTEGER REFERENCES users(id);
+
+CREATE TABLE album_permissions (
+    album_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    permission TEXT NOT NULL CHECK (permission IN ('view', 'upload', 'edit')),
+    PRIMARY KEY (album_id, user_id),
+    FOREIGN KEY (album_id) REFERENCES albums(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_album_permissions_user_id ON album_permissions(user_id);
@@ -0,0 +1,16 @@
+-- migrations/0009_api_tokens.sql
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- SHA-256 of the token; the token itself is only shown when it is created
+    token_hash TEXT NOT NULL UNIQUE,
+    -- Space-separated, e.g. 'albums:read albums:write'
+    scopes TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT,
+    last_used_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_api_tokens_user_id ON api_tokens(user_id);
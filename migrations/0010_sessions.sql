@@ -0,0 +1,26 @@
+-- migrations/0010_sessions.sql
+-- One row per login. The access JWT carries the session's jti and is checked
+-- against this table on every request; the refresh token renews it.
+CREATE TABLE sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    jti TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    -- SHA-256 of the current refresh token, replaced each time it is used
+    refresh_token_hash TEXT NOT NULL UNIQUE,
+    -- The refresh token the last refresh replaced, and when. Requests sent together
+    -- with the same expired access token all try to refresh it, so the ones that
+    -- lose the race may still use it for a short while.
+    previous_refresh_token_hash TEXT,
+    refreshed_at TEXT,
+    ip TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    last_seen_at TEXT NOT NULL DEFAULT (datetime('now')),
+    -- Pushed back every time the session is refreshed
+    expires_at TEXT NOT NULL,
+    revoked BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_sessions_user_id ON sessions(user_id);
+CREATE INDEX idx_sessions_previous_refresh_token_hash ON sessions(previous_refresh_token_hash);
@@ -0,0 +1,18 @@
+-- migrations/0011_login_events.sql
+-- Every login attempt, kept for the admin panel's audit trail and to lock out
+-- usernames and IPs that keep failing.
+CREATE TABLE login_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- As typed, so attempts on unknown usernames are recorded too. Compared without
+    -- regard to case like logins are, so each spelling doesn't get its own lockout.
+    username TEXT NOT NULL COLLATE NOCASE,
+    user_id INTEGER,
+    ip TEXT,
+    user_agent TEXT,
+    outcome TEXT NOT NULL CHECK (outcome IN ('success', 'bad_password', 'bad_code', 'locked_out', 'disabled')),
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_login_events_username ON login_events(username, created_at);
+CREATE INDEX idx_login_events_ip ON login_events(ip, created_at);
@@ -0,0 +1,15 @@
+-- migrations/0012_oidc_identities.sql
+-- Identity provider accounts allowed to sign in with single sign-on, and the
+-- user each one signs in as.
+CREATE TABLE oidc_identities (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    -- 'subject' matches the provider's `sub` claim, 'email' a verified email address
+    kind TEXT NOT NULL CHECK (kind IN ('subject', 'email')),
+    value TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    UNIQUE (kind, value),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_oidc_identities_user_id ON oidc_identities(user_id);
@@ -0,0 +1,3 @@
+-- migrations/0013_user_disabled.sql
+-- Disabled users keep their account and albums but can't sign in or use API tokens.
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,17 @@
+-- migrations/0014_site_settings.sql
+-- Site branding, edited from the admin panel. There is only ever one row.
+CREATE TABLE site_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    site_title TEXT NOT NULL DEFAULT 'Photos',
+    tagline TEXT,
+    -- Shown before the title, as in "Dylan's Photos"
+    owner_name TEXT,
+    -- JSON array of {"label": ..., "url": ...}
+    contact_links TEXT NOT NULL DEFAULT '[]',
+    -- Path or URL of the page background, none if NULL
+    background_image TEXT DEFAULT '/static/bg3.webp',
+    accent_color TEXT NOT NULL DEFAULT '#34d399',
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+INSERT INTO site_settings (id) VALUES (1);
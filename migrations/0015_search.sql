@@ -0,0 +1,45 @@
+-- migrations/0015_search.sql
+-- Descriptive metadata read from uploaded files (EXIF and XMP)
+ALTER TABLE images ADD COLUMN title TEXT;
+ALTER TABLE images ADD COLUMN caption TEXT;
+-- Comma-separated
+ALTER TABLE images ADD COLUMN keywords TEXT;
+
+-- Full-text search indexes, kept up to date by the db module. The rowid is the
+-- album or image id.
+CREATE VIRTUAL TABLE album_search USING fts5(
+    name,
+    description,
+    -- Camera and lens
+    equipment,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIRTUAL TABLE image_search USING fts5(
+    title,
+    caption,
+    keywords,
+    equipment,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO album_search (rowid, name, description, equipment)
+SELECT
+    id,
+    name,
+    description,
+    trim(coalesce(nullif(camera_model, 'Unknown'), '') || ' ' || coalesce(nullif(lens_model, 'Unknown'), ''))
+FROM albums;
+
+INSERT INTO image_search (rowid, title, caption, keywords, equipment)
+SELECT
+    id,
+    coalesce(title, original_filename),
+    caption,
+    keywords,
+    trim(
+        coalesce(nullif(camera_make, 'Unknown'), '') || ' ' ||
+        coalesce(nullif(camera_model, 'Unknown'), '') || ' ' ||
+        coalesce(nullif(lens_model, 'Unknown'), '')
+    )
+FROM images;
@@ -0,0 +1,36 @@
+-- migrations/0016_exif_values.sql
+-- Numeric forms of the EXIF text columns, for range filters. They are parsed
+-- from the strings extract_exif_metadata stores, e.g. 'ISO 3200', 'f/1.8',
+-- '1/200 s', '0.5 s', '85 mm' and '2024:05:01 18:30:00', and are NULL for
+-- 'Unknown' or anything else they can't be read from.
+ALTER TABLE images ADD COLUMN iso_value INTEGER GENERATED ALWAYS AS (
+    CASE WHEN iso LIKE 'ISO %' THEN CAST(substr(iso, 5) AS INTEGER) END
+) VIRTUAL;
+
+ALTER TABLE images ADD COLUMN aperture_value REAL GENERATED ALWAYS AS (
+    CASE WHEN aperture LIKE 'f/%' THEN CAST(substr(aperture, 3) AS REAL) END
+) VIRTUAL;
+
+-- In seconds
+ALTER TABLE images ADD COLUMN shutter_seconds REAL GENERATED ALWAYS AS (
+    CASE
+        WHEN shutter_speed LIKE '1/%' THEN 1.0 / CAST(substr(shutter_speed, 3) AS REAL)
+        WHEN shutter_speed GLOB '[0-9]*' THEN CAST(shutter_speed AS REAL)
+    END
+) VIRTUAL;
+
+ALTER TABLE images ADD COLUMN focal_length_mm REAL GENERATED ALWAYS AS (
+    CASE WHEN focal_length GLOB '[0-9]*' THEN CAST(focal_length AS REAL) END
+) VIRTUAL;
+
+-- 'YYYY-MM-DD HH:MM:SS', so it compares and works with SQLite's date functions
+ALTER TABLE images ADD COLUMN taken_at TEXT GENERATED ALWAYS AS (
+    CASE WHEN date_created GLOB '[0-9][0-9][0-9][0-9]:[0-9][0-9]:[0-9][0-9]*' THEN
+        substr(date_created, 1, 4) || '-' || substr(date_created, 6, 2) || '-' ||
+            substr(date_created, 9)
+    END
+) VIRTUAL;
+
+CREATE INDEX idx_images_camera_model ON images (camera_model);
+CREATE INDEX idx_images_lens_model ON images (lens_model);
+CREATE INDEX idx_images_taken_at ON images (taken_at);
@@ -0,0 +1,29 @@
+-- migrations/0017_tags.sql
+-- Labels for images and albums. Tags with the same slug are the same tag, so
+-- 'Sunset' and 'sunset' share one, named after whichever was used first.
+CREATE TABLE tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    -- Lowercase words joined by hyphens, used in /tags/<slug>
+    slug TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE image_tags (
+    image_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY (image_id, tag_id),
+    FOREIGN KEY (image_id) REFERENCES images(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id)
+);
+
+CREATE INDEX idx_image_tags_tag_id ON image_tags(tag_id);
+
+CREATE TABLE album_tags (
+    album_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY (album_id, tag_id),
+    FOREIGN KEY (album_id) REFERENCES albums(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id)
+);
+
+CREATE INDEX idx_album_tags_tag_id ON album_tags(tag_id);
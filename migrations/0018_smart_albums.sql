@@ -0,0 +1,16 @@
+-- migrations/0018_smart_albums.sql
+-- Star ratings from 1 to 5, or 0 for images that haven't been rated
+ALTER TABLE images ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_images_rating ON images (rating);
+
+-- Albums made of whichever images in public albums match a filter, instead of
+-- images uploaded to them, so they fill themselves as photos are added
+CREATE TABLE smart_albums (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    description TEXT,
+    -- An ImageFilter as JSON
+    filter TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,360 @@
+use async_zip::base::write::ZipFileWriter;
+use axum::body::Body;
+use async_zip::tokio::read::fs::ZipFileReader;
+use async_zip::{Compression, ZipEntryBuilder};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWrite;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::io::ReaderStream;
+
+use crate::config::ImageConfig;
+use crate::db::{self, create_album, find_album_by_name, update_album_metadata};
+use crate::types::{Album, CreateAlbumRequest, Image, Visibility};
+use crate::import::{import_batch, IMPORT_BATCH_SIZE};
+use crate::utils::{create_album_directory, ImageQuality};
+
+/// Bumped whenever the manifest layout changes in a way older versions can't read.
+const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// The `albums` and `images` rows of an export, stored as `manifest.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub albums: Vec<Album>,
+    pub images: Vec<Image>,
+    /// Tag names by album id
+    #[serde(default)]
+    pub album_tags: HashMap<i64, Vec<String>>,
+    /// Tag names by image id
+    #[serde(default)]
+    pub image_tags: HashMap<i64, Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct ArchiveImportSummary {
+    pub albums: usize,
+    pub images_imported: usize,
+    pub images_skipped: usize,
+    pub images_failed: usize,
+}
+
+/// Path of an original inside the archive.
+fn original_entry_name(image: &Image) -> String {
+    format!("originals/{}/{}", image.album_id, image.filename)
+}
+
+/// Path of an image on disk for the given quality.
+pub fn image_path(album_id: i64, quality: ImageQuality, filename: &str) -> PathBuf {
+    PathBuf::from("uploads")
+        .join(album_id.to_string())
+        .join(quality.as_str())
+        .join(filename)
+}
+
+/// Streams a file from disk into a new, uncompressed zip entry.
+pub async fn write_file_entry<W>(
+    zip: &mut ZipFileWriter<tokio_util::compat::Compat<W>>,
+    name: &str,
+    path: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    W: AsyncWrite + Unpin,
+{
+    let file = fs::File::open(path).await?;
+    // JPEGs don't compress further, so entries are stored as-is
+    let builder = ZipEntryBuilder::new(name.to_string().into(), Compression::Stored);
+    let mut entry = zip.write_entry_stream(builder).await?;
+    futures::io::copy(&mut file.compat(), &mut entry).await?;
+    entry.close().await?;
+    Ok(())
+}
+
+/// Returns a response body that streams a zip of `entries` (name in the archive,
+/// path on disk) as it is written, without buffering it in memory or on disk.
+pub fn stream_zip(entries: Vec<(String, PathBuf)>) -> Body {
+    let (writer, reader) = tokio::io::duplex(64 * 1024);
+
+    tokio::spawn(async move {
+        let mut zip = ZipFileWriter::with_tokio(writer);
+        for (name, path) in entries {
+            // Fails when the client disconnects, which ends the stream
+            if let Err(e) = write_file_entry(&mut zip, &name, &path).await {
+                eprintln!("Stopped streaming zip at {}: {}", name, e);
+                return;
+            }
+        }
+        if let Err(e) = zip.close().await {
+            eprintln!("Failed to finish zip stream: {}", e);
+        }
+    });
+
+    Body::from_stream(ReaderStream::new(reader))
+}
+
+/// Picks a unique name inside an archive for each image, preferring the name it
+/// was uploaded with.
+pub fn archive_entry_names(images: &[Image]) -> Vec<String> {
+    let mut used = HashSet::new();
+    images
+        .iter()
+        .map(|image| {
+            let name = image.original_filename.as_deref().unwrap_or(&image.filename);
+            let path = Path::new(name);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+
+            let mut candidate = name.to_string();
+            let mut counter = 1;
+            while !used.insert(candidate.to_lowercase()) {
+                candidate = format!("{}-{}.{}", stem, counter, extension);
+                counter += 1;
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// Exports one album, or every album when `album_id` is `None`, to a zip at `out`
+/// containing the originals and a JSON manifest of their database rows.
+pub async fn export_archive(
+    pool: &SqlitePool,
+    album_id: Option<i64>,
+    out: &Path,
+) -> Result<Manifest, Box<dyn Error + Send + Sync>> {
+    let album_ids = match album_id {
+        Some(id) => vec![id],
+        None => db::get_album_ids(pool).await?,
+    };
+
+    let mut manifest = Manifest {
+        version: MANIFEST_VERSION,
+        albums: Vec::new(),
+        images: Vec::new(),
+        album_tags: HashMap::new(),
+        image_tags: HashMap::new(),
+    };
+    for id in album_ids {
+        let (album, images) = db::get_album_with_images(pool, id).await?;
+        let album_tags = db::get_album_tags(pool, id).await?;
+        if !album_tags.is_empty() {
+            let names = album_tags.into_iter().map(|tag| tag.name).collect();
+            manifest.album_tags.insert(id, names);
+        }
+        manifest.albums.push(album);
+
+        for tagged in db::with_image_tags(pool, images).await? {
+            if !tagged.tags.is_empty() {
+                let names = tagged.tags.into_iter().map(|tag| tag.name).collect();
+                manifest.image_tags.insert(tagged.image.id, names);
+            }
+            manifest.images.push(tagged.image);
+        }
+    }
+
+    let file = fs::File::create(out).await?;
+    let mut zip = ZipFileWriter::with_tokio(file);
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let builder = ZipEntryBuilder::new(MANIFEST_NAME.to_string().into(), Compression::Stored);
+    zip.write_entry_whole(builder, &manifest_json).await?;
+
+    for image in &manifest.images {
+        let path = image_path(image.album_id, ImageQuality::Full, &image.filename);
+        write_file_entry(&mut zip, &original_entry_name(image), &path).await?;
+    }
+
+    zip.close().await?;
+    Ok(manifest)
+}
+
+/// Recreates the albums in an exported archive, running each original through
+/// the normal processing pipeline.
+///
+/// Albums are matched by name so an archive can be imported more than once;
+/// images already present in the matching album are skipped. Tags are added to
+/// the albums and to the images that were imported, which also get their ratings
+/// back.
+pub async fn import_archive(
+    pool: &SqlitePool,
+    image_config: &ImageConfig,
+    path: &Path,
+) -> Result<ArchiveImportSummary, Box<dyn Error + Send + Sync>> {
+    let reader = ZipFileReader::new(path).await?;
+
+    let entry_index: HashMap<String, usize> = reader
+        .file()
+        .entries()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| Some((entry.filename().as_str().ok()?.to_string(), i)))
+        .collect();
+
+    let manifest_index = *entry_index
+        .get(MANIFEST_NAME)
+        .ok_or("Archive does not contain a manifest.json")?;
+    let mut manifest_json = String::new();
+    reader
+        .reader_with_entry(manifest_index)
+        .await?
+        .read_to_string_checked(&mut manifest_json)
+        .await?;
+    let manifest: Manifest = serde_json::from_str(&manifest_json)?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(format!("Unsupported manifest version {}", manifest.version).into());
+    }
+
+    let mut summary = ArchiveImportSummary::default();
+
+    for album in &manifest.albums {
+        let album_id = match find_album_by_name(pool, &album.name).await? {
+            Some(id) => id,
+            None => {
+                let request = CreateAlbumRequest {
+                    name: album.name.clone(),
+                    description: album.description.clone(),
+                    date: album.date.clone(),
+                    allow_download: album.allow_download,
+                    // Password hashes aren't exported, so locked albums come back private
+                    visibility: match album.visibility {
+                        Visibility::Password => Visibility::Private,
+                        visibility => visibility,
+                    },
+                    password: None,
+                };
+                create_album(pool, &request, None).await?
+            }
+        };
+        create_album_directory(album_id).await?;
+        summary.albums += 1;
+
+        if let Some(names) = manifest.album_tags.get(&album.id) {
+            let mut tag_ids: Vec<i64> = db::get_album_tags(pool, album_id)
+                .await?
+                .into_iter()
+                .map(|tag| tag.id)
+                .collect();
+            for name in names {
+                if let Some(tag) = db::get_or_create_tag(pool, name).await? {
+                    tag_ids.push(tag.id);
+                }
+            }
+            db::set_album_tags(pool, album_id, &tag_ids).await?;
+        }
+
+        let mut known: HashSet<String> = db::get_album_checksums(pool, album_id)
+            .await?
+            .into_iter()
+            .collect();
+
+        let images: Vec<&Image> = manifest
+            .images
+            .iter()
+            .filter(|image| image.album_id == album.id)
+            .collect();
+
+        // Checksums of the stored images, to find them afterwards
+        let mut imported: Vec<(&Image, String)> = Vec::new();
+        for chunk in images.chunks(IMPORT_BATCH_SIZE) {
+            let mut batch = Vec::new();
+            for &image in chunk {
+                let Some(&index) = entry_index.get(&original_entry_name(image)) else {
+                    eprintln!("Missing original for {} in archive", image.filename);
+                    summary.images_failed += 1;
+                    continue;
+                };
+
+                let mut data = Vec::new();
+                reader
+                    .reader_with_entry(index)
+                    .await?
+                    .read_to_end_checked(&mut data)
+                    .await?;
+
+                let name = image
+                    .original_filename
+                    .clone()
+                    .unwrap_or_else(|| image.filename.clone());
+                batch.push((image, name, data));
+            }
+
+            let outcome = import_batch(pool, image_config, album_id, &mut known, batch).await?;
+            summary.images_imported += outcome.imported.len();
+            summary.images_skipped += outcome.skipped.len();
+            summary.images_failed += outcome.failed.len();
+            imported.extend(outcome.imported);
+        }
+
+        update_album_metadata(pool, album_id).await?;
+        restore_image_tags(pool, album_id, &manifest, &imported).await?;
+        restore_image_ratings(pool, album_id, &imported).await?;
+    }
+
+    Ok(summary)
+}
+
+/// Gives the images imported into `album_id` the tags they had in the archive.
+async fn restore_image_tags(
+    pool: &SqlitePool,
+    album_id: i64,
+    manifest: &Manifest,
+    imported: &[(&Image, String)],
+) -> Result<(), sqlx::Error> {
+    if !imported.iter().any(|(image, _)| manifest.image_tags.contains_key(&image.id)) {
+        return Ok(());
+    }
+    let image_ids = db::get_album_image_ids_by_checksum(pool, album_id).await?;
+
+    let mut tagged: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (image, checksum) in imported {
+        let (Some(names), Some(&image_id)) =
+            (manifest.image_tags.get(&image.id), image_ids.get(checksum))
+        else {
+            continue;
+        };
+        for name in names {
+            if let Some(tag) = db::get_or_create_tag(pool, name).await? {
+                tagged.entry(tag.id).or_default().push(image_id);
+            }
+        }
+    }
+
+    let mut reindex: Vec<i64> = Vec::new();
+    for (tag_id, image_ids) in tagged {
+        db::add_image_tag(pool, album_id, &image_ids, tag_id).await?;
+        reindex.extend(image_ids);
+    }
+    reindex.sort_unstable();
+    reindex.dedup();
+    // Tags are part of the search index
+    db::reindex_images(pool, album_id, &reindex).await
+}
+
+/// Gives the images imported into `album_id` the ratings they had in the archive.
+async fn restore_image_ratings(
+    pool: &SqlitePool,
+    album_id: i64,
+    imported: &[(&Image, String)],
+) -> Result<(), sqlx::Error> {
+    if imported.iter().all(|(image, _)| image.rating == 0) {
+        return Ok(());
+    }
+    let image_ids = db::get_album_image_ids_by_checksum(pool, album_id).await?;
+
+    let mut rated: HashMap<i64, Vec<i64>> = HashMap::new();
+    for (image, checksum) in imported {
+        if let Some(&image_id) = image_ids.get(checksum).filter(|_| image.rating != 0) {
+            rated.entry(image.rating).or_default().push(image_id);
+        }
+    }
+    for (rating, image_ids) in rated {
+        db::set_image_rating(pool, album_id, &image_ids, rating).await?;
+    }
+
+    Ok(())
+}
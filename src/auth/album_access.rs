@@ -0,0 +1,132 @@
+use crate::auth::middleware::current_user;
+use crate::auth::permissions::album_permission;
+use crate::db::get_valid_share_link;
+use crate::types::{AlbumAccessClaims, AppState, Visibility};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_cookies::cookie::time::{Duration, OffsetDateTime};
+use tower_cookies::cookie::CookieBuilder;
+use tower_cookies::Cookies;
+
+/// How long an unlocked album stays unlocked, in seconds.
+const ALBUM_ACCESS_DURATION: i64 = 24 * 3600;
+
+/// Each unlocked album gets its own cookie, so unlocking one never unlocks another.
+fn access_cookie_name(album_id: i64) -> String {
+    format!("album_access_{}", album_id)
+}
+
+/// What the current visitor is allowed to do with an album they can see.
+#[derive(Debug, Clone, Copy)]
+pub struct AlbumAccess {
+    /// The share link used to open the album allows downloads, even if the album doesn't
+    pub share_allows_download: bool,
+}
+
+/// How the visitor's access cookie for an album was obtained.
+enum AccessGrant {
+    Password,
+    ShareLink { allow_download: bool },
+}
+
+/// Checks whether the current visitor may see an album with the given visibility.
+///
+/// Logged-in users can see every album they have at least view permission on.
+/// Password-protected albums also accept the cookie issued after entering the password,
+/// and any album accepts the cookie issued by a share link for as long as the link stays valid.
+pub async fn album_access(
+    cookies: &Cookies,
+    state: &AppState,
+    album_id: i64,
+    visibility: Visibility,
+) -> Option<AlbumAccess> {
+    let grant = access_grant(cookies, state, album_id).await;
+    let share_allows_download =
+        matches!(grant, Some(AccessGrant::ShareLink { allow_download: true }));
+
+    let allowed = match visibility {
+        Visibility::Public | Visibility::Unlisted => true,
+        Visibility::Password => grant.is_some() || can_view(cookies, state, album_id).await,
+        Visibility::Private => {
+            matches!(grant, Some(AccessGrant::ShareLink { .. }))
+                || can_view(cookies, state, album_id).await
+        }
+    };
+
+    allowed.then_some(AlbumAccess {
+        share_allows_download,
+    })
+}
+
+/// Whether the visitor is logged in as a user who may view the album.
+async fn can_view(cookies: &Cookies, state: &AppState, album_id: i64) -> bool {
+    let Some(user) = current_user(cookies, state).await else {
+        return false;
+    };
+    matches!(
+        album_permission(&state.pool, &user, album_id).await,
+        Ok(Some(_))
+    )
+}
+
+async fn access_grant(cookies: &Cookies, state: &AppState, album_id: i64) -> Option<AccessGrant> {
+    let token = cookies.get(&access_cookie_name(album_id))?;
+    let claims = decode::<AlbumAccessClaims>(
+        token.value(),
+        &DecodingKey::from_secret(state.jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+    .ok()?
+    .claims;
+    if claims.album_id != album_id {
+        return None;
+    }
+
+    match claims.share_link_id {
+        // Revoking or expiring a link also locks out visitors who already opened it
+        Some(link_id) => get_valid_share_link(&state.pool, link_id, album_id)
+            .await
+            .ok()
+            .flatten()
+            .map(|link| AccessGrant::ShareLink {
+                allow_download: link.allow_download,
+            }),
+        None => Some(AccessGrant::Password),
+    }
+}
+
+/// Issues the cookie that unlocks an album after the visitor has entered its
+/// password or opened one of its share links.
+pub fn grant_album_access(
+    cookies: &Cookies,
+    jwt_secret: &str,
+    album_id: i64,
+    share_link_id: Option<i64>,
+) -> Result<(), jsonwebtoken::errors::Error> {
+    let expiration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize
+        + ALBUM_ACCESS_DURATION as usize;
+
+    let claims = AlbumAccessClaims {
+        album_id,
+        exp: expiration,
+        share_link_id,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?;
+
+    let cookie = CookieBuilder::new(access_cookie_name(album_id), token)
+        .http_only(true)
+        .secure(true)
+        .path("/")
+        .max_age(Duration::seconds(ALBUM_ACCESS_DURATION))
+        .expires(OffsetDateTime::now_utc() + Duration::seconds(ALBUM_ACCESS_DURATION))
+        .build();
+    cookies.add(cookie);
+    Ok(())
+}
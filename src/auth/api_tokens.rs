@@ -0,0 +1,78 @@
+use crate::db::{get_user, use_api_token};
+use crate::types::{ApiScope, AppState, CurrentUser};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::utils::{generate_token, hash_token};
+use std::sync::Arc;
+
+/// Prefix that makes tokens easy to recognise, e.g. by secret scanners.
+const TOKEN_PREFIX: &str = "pg_";
+
+/// Generates a new random API token.
+pub fn generate_api_token() -> String {
+    format!("{}{}", TOKEN_PREFIX, generate_token())
+}
+
+/// Returns the API token in a request's `Authorization: Bearer` header, if it has
+/// the shape of one.
+pub fn bearer_api_token(headers: &HeaderMap) -> Option<&str> {
+    let token = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim();
+    let random = token.strip_prefix(TOKEN_PREFIX)?;
+    (random.len() == 64 && random.bytes().all(|b| b.is_ascii_hexdigit())).then_some(token)
+}
+
+/// Middleware for the API routes that accept personal API tokens.
+///
+/// Requests with an `Authorization: Bearer` header are authenticated by the token,
+/// which makes its user the request's `CurrentUser`, limited to the token's scopes.
+/// Requests without one fall through to the `auth_token` cookie as usual.
+pub async fn api_token_auth(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(authorization) = request.headers().get(header::AUTHORIZATION) else {
+        return next.run(request).await;
+    };
+    let Some(token) = authorization
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return (StatusCode::UNAUTHORIZED, "Expected a Bearer token").into_response();
+    };
+
+    let (user_id, scopes) = match use_api_token(&state.pool, &hash_token(token.trim())).await
+    {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            return (StatusCode::UNAUTHORIZED, "Invalid or expired API token").into_response()
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let user = match get_user(&state.pool, user_id).await {
+        Ok(Some(user)) if !user.disabled => user,
+        Ok(_) => {
+            return (StatusCode::UNAUTHORIZED, "Invalid or expired API token").into_response()
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    request.extensions_mut().insert(CurrentUser {
+        id: user.id,
+        username: user.username,
+        role: user.role,
+        scopes: Some(scopes.split_whitespace().filter_map(ApiScope::parse).collect()),
+        session_id: None,
+    });
+    next.run(request).await
+}
@@ -0,0 +1,175 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_cookies::cookie::{CookieBuilder, SameSite};
+use tower_cookies::Cookies;
+
+use crate::auth::api_tokens::bearer_api_token;
+use crate::utils::generate_token;
+
+/// Cookie holding the CSRF token. Scripts on our own pages read it and send it
+/// back in `CSRF_HEADER`, which another site can't do.
+const CSRF_COOKIE: &str = "csrf_token";
+const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Middleware that rejects cross-site requests to state-changing routes.
+///
+/// Every `POST`, `PUT`, `PATCH` and `DELETE` must come from one of our own pages,
+/// judged by the `Sec-Fetch-Site` or `Origin` header. Requests to `/api/` routes
+/// must also send the `csrf_token` cookie's value in the `X-CSRF-Token` header.
+///
+pub async fn csrf_protection(cookies: Cookies, request: Request, next: Next) -> Response {
+    match check_request(&cookies, &request) {
+        Ok(()) => next.run(request).await,
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// `csrf_protection` for the routes behind `api_token_auth`.
+///
+/// Requests authenticated by an API token are exempt: browsers won't send an
+/// `Authorization` header cross-site without a CORS preflight, which this server
+/// never allows. Any other `Authorization` header doesn't authenticate the request,
+/// so it gets no exemption either.
+pub async fn token_api_csrf_protection(
+    cookies: Cookies,
+    request: Request,
+    next: Next,
+) -> Response {
+    if bearer_api_token(request.headers()).is_some() {
+        return next.run(request).await;
+    }
+    csrf_protection(cookies, request, next).await
+}
+
+fn check_request(cookies: &Cookies, request: &Request) -> Result<(), (StatusCode, &'static str)> {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        if cookies.get(CSRF_COOKIE).is_none() {
+            set_csrf_cookie(cookies);
+        }
+        return Ok(());
+    }
+
+    let headers = request.headers();
+    if !is_same_origin(headers) {
+        return Err((StatusCode::FORBIDDEN, "Cross-site request blocked"));
+    }
+    if request.uri().path().starts_with("/api/") {
+        let sent = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok());
+        let expected = cookies.get(CSRF_COOKIE);
+        if sent.is_none() || sent != expected.as_ref().map(|cookie| cookie.value()) {
+            return Err((StatusCode::FORBIDDEN, "Missing or invalid CSRF token"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks where a request came from, preferring `Sec-Fetch-Site` and falling back
+/// to comparing `Origin` with `Host`. Requests with neither aren't from a browser
+/// page, or are from one too old to send them, and are let through.
+fn is_same_origin(headers: &HeaderMap) -> bool {
+    if let Some(site) = headers.get("sec-fetch-site") {
+        // "none" means the user started the request themselves, e.g. from the address bar
+        return matches!(site.as_bytes(), b"same-origin" | b"none");
+    }
+
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let origin_host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, host)| host);
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    origin_host.is_some() && origin_host == host
+}
+
+fn set_csrf_cookie(cookies: &Cookies) {
+    let cookie = CookieBuilder::new(CSRF_COOKIE, generate_token())
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .path("/")
+        .build();
+    cookies.add(cookie);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::post, Router};
+    use tower::ServiceExt;
+    use tower_cookies::CookieManagerLayer;
+
+    const API_TOKEN: &str =
+        "pg_0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    /// A cookie-only route and a route that also takes API tokens, layered like in `main`.
+    fn app() -> Router {
+        Router::new()
+            .route("/api/users", post(|| async { "ok" }))
+            .layer(middleware::from_fn(csrf_protection))
+            .merge(
+                Router::new()
+                    .route("/api/albums", post(|| async { "ok" }))
+                    .layer(middleware::from_fn(token_api_csrf_protection)),
+            )
+            .layer(CookieManagerLayer::new())
+    }
+
+    async fn post_status(
+        path: &str,
+        authorization: Option<&str>,
+        csrf: Option<&str>,
+    ) -> StatusCode {
+        // Requests sending the CSRF token come from our own pages, the others from
+        // another site
+        let site = if csrf.is_some() { "same-origin" } else { "cross-site" };
+        let mut request = Request::post(path)
+            .header(header::COOKIE, "auth_token=session; csrf_token=abc")
+            .header("sec-fetch-site", site);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        if let Some(csrf) = csrf {
+            request = request.header(CSRF_HEADER, csrf);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn junk_authorization_header_gets_no_exemption() {
+        assert_eq!(post_status("/api/users", Some("junk"), None).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_status("/api/albums", Some("junk"), None).await, StatusCode::FORBIDDEN);
+        let bearer = "Bearer pg_not-a-token";
+        assert_eq!(post_status("/api/albums", Some(bearer), None).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn api_tokens_are_only_exempt_on_token_routes() {
+        let bearer = format!("Bearer {}", API_TOKEN);
+        assert_eq!(post_status("/api/albums", Some(&bearer), None).await, StatusCode::OK);
+        assert_eq!(post_status("/api/users", Some(&bearer), None).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn same_origin_requests_need_the_csrf_token() {
+        assert_eq!(post_status("/api/users", None, Some("abc")).await, StatusCode::OK);
+        assert_eq!(post_status("/api/users", None, Some("xyz")).await, StatusCode::FORBIDDEN);
+    }
+}
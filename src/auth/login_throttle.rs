@@ -0,0 +1,80 @@
+use crate::db::{
+    delete_old_login_events, get_ip_login_failures, get_username_login_failures,
+    record_login_event,
+};
+use crate::types::LoginOutcome;
+use sqlx::SqlitePool;
+
+/// Failed logins allowed for a username before it is locked out.
+const USERNAME_FAILURE_LIMIT: i64 = 5;
+/// Failed logins allowed from an IP address, higher because several people may
+/// share one.
+const IP_FAILURE_LIMIT: i64 = 20;
+/// The first lockout, in seconds. Every further failure doubles it.
+const BASE_LOCKOUT: i64 = 30;
+/// The longest lockout, in seconds.
+const MAX_LOCKOUT: i64 = 3600;
+/// How long login attempts are kept for the audit trail.
+const LOGIN_EVENT_RETENTION_DAYS: i64 = 90;
+
+/// Checks whether logins for `username`, or from `ip`, are locked out after too
+/// many failures.
+///
+/// # Returns
+/// * `Option<i64>` - How many seconds are left until they may try again.
+///
+pub async fn lockout_remaining(
+    pool: &SqlitePool,
+    username: &str,
+    ip: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let (failures, seconds_since_last) = get_username_login_failures(pool, username).await?;
+    let mut remaining = lockout_left(failures, seconds_since_last, USERNAME_FAILURE_LIMIT);
+
+    if let Some(ip) = ip {
+        let (failures, seconds_since_last) = get_ip_login_failures(pool, ip).await?;
+        remaining = remaining.max(lockout_left(failures, seconds_since_last, IP_FAILURE_LIMIT));
+    }
+
+    Ok(remaining)
+}
+
+/// Records a login attempt. Failing to record one isn't worth failing the login
+/// over, so errors are only logged.
+pub async fn record_login(
+    pool: &SqlitePool,
+    username: &str,
+    user_id: Option<i64>,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+    outcome: LoginOutcome,
+) {
+    if let Err(e) = record_login_event(pool, username, user_id, ip, user_agent, outcome).await {
+        eprintln!("Failed to record login event: {}", e);
+    }
+    if outcome == LoginOutcome::Success {
+        if let Err(e) = delete_old_login_events(pool, LOGIN_EVENT_RETENTION_DAYS).await {
+            eprintln!("Failed to delete old login events: {}", e);
+        }
+    }
+}
+
+/// Message shown on the login page while locked out.
+pub fn lockout_message(seconds: i64) -> String {
+    let minutes = (seconds + 59) / 60;
+    format!(
+        "Too many failed attempts, try again in {} minute{}",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    )
+}
+
+fn lockout_left(failures: i64, seconds_since_last: Option<i64>, limit: i64) -> Option<i64> {
+    if failures < limit {
+        return None;
+    }
+    let doublings = (failures - limit).min(32) as u32;
+    let lockout = BASE_LOCKOUT.saturating_mul(1 << doublings).min(MAX_LOCKOUT);
+    let remaining = lockout - seconds_since_last.unwrap_or(0);
+    (remaining > 0).then_some(remaining)
+}
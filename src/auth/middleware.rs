@@ -1,44 +1,109 @@
-use crate::types::{AppState, Claims};
-use axum::{extract::State, response::Redirect};
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use crate::auth::sessions::authenticate_session;
+use crate::db::get_user;
+use crate::types::{ApiScope, AppState, CurrentUser, Role};
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::Redirect,
+};
 use std::sync::Arc;
 use tower_cookies::Cookies;
 
-/// Decodes the provided JWT token to extract the claims.
+/// Looks up the user whose session the request's cookies belong to, refreshing
+/// the session if its access token has expired.
 ///
 /// # Arguments
-/// * `token` - JWT token to be decoded.
-/// * `jwt_secret` - Secret key used for decoding the token.
+/// * `cookies` - Cookies extracted from the request.
+/// * `state` - Application state containing the JWT secret key and database pool.
 ///
 /// # Returns
-/// * `bool` - `true` if the token is valid, `false` otherwise.
+/// * `Option<CurrentUser>` - The user if the session is valid and they still exist and
+///   aren't disabled, `None` otherwise.
 ///
-pub async fn is_authenticated(cookies: &Cookies, jwt_secret: &str) -> bool {
-    if let Some(token) = cookies.get("auth_token") {
-        let claims = decode::<Claims>(
-            token.value(),
-            &DecodingKey::from_secret(jwt_secret.as_bytes()),
-            &Validation::default(),
-        );
-
-        return claims.is_ok();
-    }
-    false
+pub async fn current_user(cookies: &Cookies, state: &AppState) -> Option<CurrentUser> {
+    let (session_id, user_id) = authenticate_session(cookies, state).await?;
+
+    // Loaded on every request so role changes, and deleted or disabled users, take
+    // effect immediately
+    let user = get_user(&state.pool, user_id)
+        .await
+        .ok()?
+        .filter(|user| !user.disabled)?;
+    Some(CurrentUser {
+        id: user.id,
+        username: user.username,
+        role: user.role,
+        scopes: None,
+        session_id: Some(session_id),
+    })
 }
 
-/// Middleware to require authentication for a route.
+/// Checks whether the request comes from a logged-in user.
 ///
 /// # Arguments
 /// * `cookies` - Cookies extracted from the request.
-/// * `state` - Application state containing the JWT secret key.
+/// * `state` - Application state containing the JWT secret key and database pool.
 ///
 /// # Returns
-/// * `Result<(), Redirect>` - `Ok(())` if authenticated, `Err(Redirect::to("/login"))` otherwise.
+/// * `bool` - `true` if the token is valid, `false` otherwise.
 ///
-pub async fn require_auth(cookies: Cookies, state: State<Arc<AppState>>) -> Result<(), Redirect> {
-    if is_authenticated(&cookies, &state.jwt_secret).await {
-        Ok(())
-    } else {
-        Err(Redirect::to("/login"))
+pub async fn is_authenticated(cookies: &Cookies, state: &AppState) -> bool {
+    current_user(cookies, state).await.is_some()
+}
+
+/// Extractor for routes that require a logged-in user, redirecting to the login
+/// page otherwise. On routes behind `api_token_auth` this is the API token's user.
+impl FromRequestParts<Arc<AppState>> for CurrentUser {
+    type Rejection = Redirect;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<CurrentUser>() {
+            return Ok(user.clone());
+        }
+
+        let cookies = Cookies::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Redirect::to("/login"))?;
+
+        current_user(&cookies, state)
+            .await
+            .ok_or_else(|| Redirect::to("/login"))
+    }
+}
+
+impl CurrentUser {
+    /// Checks that the user's role is at least `role`.
+    ///
+    /// # Returns
+    /// * `Result<(), (StatusCode, &str)>` - `Ok(())` if allowed, a `403 Forbidden` response otherwise.
+    ///
+    pub fn require(&self, role: Role) -> Result<(), (StatusCode, &'static str)> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                "You don't have permission to do that",
+            ))
+        }
+    }
+
+    /// Checks that an API token used for the request has `scope`. Browser sessions
+    /// aren't limited by scopes.
+    ///
+    /// # Returns
+    /// * `Result<(), (StatusCode, &str)>` - `Ok(())` if allowed, a `403 Forbidden` response otherwise.
+    ///
+    pub fn require_scope(&self, scope: ApiScope) -> Result<(), (StatusCode, &'static str)> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err((
+                StatusCode::FORBIDDEN,
+                "This API token doesn't have the scope needed for that",
+            )),
+            _ => Ok(()),
+        }
     }
 }
@@ -1 +1,11 @@
-pub mod middleware;
\ No newline at end of file
+pub mod album_access;
+pub mod api_tokens;
+pub mod csrf;
+pub mod login_throttle;
+pub mod middleware;
+pub mod oidc;
+pub mod password;
+pub mod permissions;
+pub mod sessions;
+pub mod signed_urls;
+pub mod two_factor;
@@ -0,0 +1,182 @@
+use crate::types::OidcConfig;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+
+/// Scopes asked for; `email` lets accounts be matched by their email address.
+const SCOPES: &str = "openid email profile";
+
+/// The parts of the provider's discovery document this uses.
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims of a verified ID token that identify the account.
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    email: Option<String>,
+    /// Usually a boolean, but some providers send a string
+    email_verified: Option<serde_json::Value>,
+    nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// The account's email address, lowercased, if the provider has verified it.
+    pub fn verified_email(&self) -> Option<String> {
+        let verified = match &self.email_verified {
+            Some(serde_json::Value::Bool(verified)) => *verified,
+            Some(serde_json::Value::String(verified)) => verified == "true",
+            _ => false,
+        };
+        self.email
+            .as_deref()
+            .filter(|_| verified)
+            .map(|email| email.to_lowercase())
+    }
+}
+
+/// A login to send the user to the identity provider with. `state`, `nonce` and
+/// `code_verifier` have to be kept until the callback.
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// Starts an authorization code login with PKCE.
+pub async fn authorization_request(
+    config: &OidcConfig,
+) -> Result<AuthorizationRequest, Box<dyn Error + Send + Sync>> {
+    let metadata = provider_metadata(config).await?;
+
+    let state = random_token();
+    let nonce = random_token();
+    let code_verifier = random_token();
+    let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+    let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &config.client_id)
+        .append_pair("redirect_uri", &config.redirect_url)
+        .append_pair("scope", SCOPES)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &code_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(AuthorizationRequest {
+        url: url.into(),
+        state,
+        nonce,
+        code_verifier,
+    })
+}
+
+/// Exchanges the code the provider sent back for an ID token, and verifies the
+/// token's signature, issuer, audience, expiry and nonce.
+pub async fn exchange_code(
+    config: &OidcConfig,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims, Box<dyn Error + Send + Sync>> {
+    let metadata = provider_metadata(config).await?;
+
+    let mut request = config.http.post(&metadata.token_endpoint).form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("code_verifier", code_verifier),
+    ]);
+    if let Some(client_secret) = &config.client_secret {
+        request = request.basic_auth(&config.client_id, Some(client_secret));
+    }
+    let tokens: TokenResponse = request.send().await?.error_for_status()?.json().await?;
+
+    let header = decode_header(&tokens.id_token)?;
+    // Only accept tokens signed with the provider's public keys
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err("ID token isn't signed with a public key".into());
+    }
+    let keys: JwkSet = config
+        .http
+        .get(&metadata.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let key = match &header.kid {
+        Some(kid) => keys.find(kid),
+        None => keys.keys.first(),
+    }
+    .ok_or("ID token is signed with an unknown key")?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&config.client_id]);
+    validation.set_issuer(&[&config.issuer]);
+    let claims = decode::<IdTokenClaims>(
+        &tokens.id_token,
+        &DecodingKey::from_jwk(key)?,
+        &validation,
+    )?
+    .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err("ID token nonce doesn't match the login".into());
+    }
+    Ok(claims)
+}
+
+async fn provider_metadata(
+    config: &OidcConfig,
+) -> Result<ProviderMetadata, Box<dyn Error + Send + Sync>> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        config.issuer.trim_end_matches('/')
+    );
+    let metadata: ProviderMetadata = config
+        .http
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if metadata.issuer != config.issuer {
+        return Err(format!(
+            "Provider says its issuer is {}, not {}",
+            metadata.issuer, config.issuer
+        )
+        .into());
+    }
+    Ok(metadata)
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
@@ -0,0 +1,53 @@
+use crate::db::{get_album_owner, get_album_permission};
+use crate::types::{AlbumPermission, CurrentUser, Role};
+use axum::http::StatusCode;
+use sqlx::SqlitePool;
+
+/// Works out what a user may do with an album.
+///
+/// Site owners and the album's creator own it; everyone else gets whatever they
+/// were granted in `album_permissions`. Albums without an owner belong to site owners only.
+///
+/// # Returns
+/// * `Result<Option<AlbumPermission>, sqlx::Error>` - `None` if the album doesn't exist or the
+///   user has no permission on it.
+///
+pub async fn album_permission(
+    pool: &SqlitePool,
+    user: &CurrentUser,
+    album_id: i64,
+) -> Result<Option<AlbumPermission>, sqlx::Error> {
+    let owner_id = match get_album_owner(pool, album_id).await? {
+        Some(owner_id) => owner_id,
+        None => return Ok(None),
+    };
+
+    if user.role == Role::Owner || owner_id == Some(user.id) {
+        return Ok(Some(AlbumPermission::Owner));
+    }
+
+    get_album_permission(pool, album_id, user.id).await
+}
+
+/// Checks that a user has at least `required` permission on an album.
+///
+/// # Returns
+/// * `Result<(), (StatusCode, String)>` - `Ok(())` if allowed, `404 Not Found` if the album
+///   doesn't exist or the user can't see it, `403 Forbidden` otherwise.
+///
+pub async fn require_album_permission(
+    pool: &SqlitePool,
+    user: &CurrentUser,
+    album_id: i64,
+    required: AlbumPermission,
+) -> Result<(), (StatusCode, String)> {
+    match album_permission(pool, user, album_id).await {
+        Ok(Some(permission)) if permission >= required => Ok(()),
+        Ok(Some(_)) => Err((
+            StatusCode::FORBIDDEN,
+            "You don't have permission to do that".to_string(),
+        )),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Album not found".to_string())),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
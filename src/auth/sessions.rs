@@ -0,0 +1,162 @@
+use crate::db::{
+    create_session, delete_dead_sessions, refresh_session, use_previous_refresh_token, use_session,
+};
+use crate::types::{AppState, Claims};
+use crate::utils::{generate_token, hash_token};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_cookies::cookie::time::{Duration, OffsetDateTime};
+use tower_cookies::cookie::CookieBuilder;
+use tower_cookies::{Cookie, Cookies};
+use uuid::Uuid;
+
+/// How long an access token is valid for, in seconds, before it is renewed from
+/// the refresh token.
+const ACCESS_TOKEN_DURATION: i64 = 15 * 60;
+/// How long a session lasts without being used, in seconds. Every refresh pushes
+/// the expiry back by this much.
+const SESSION_IDLE_DURATION: i64 = 7 * 24 * 3600;
+/// How long a refresh token still works after it has been swapped for a new one, in
+/// seconds, for requests that were already on their way with it.
+const REFRESH_GRACE_DURATION: i64 = 30;
+
+const ACCESS_COOKIE: &str = "auth_token";
+const REFRESH_COOKIE: &str = "refresh_token";
+
+/// Logs a user in: records a new session and sets its access and refresh token cookies.
+pub async fn start_session(
+    cookies: &Cookies,
+    state: &AppState,
+    user_id: i64,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    delete_dead_sessions(&state.pool, user_id).await?;
+
+    let jti = Uuid::new_v4().to_string();
+    let refresh_token = generate_token();
+    create_session(
+        &state.pool,
+        &jti,
+        user_id,
+        &hash_token(&refresh_token),
+        ip,
+        user_agent,
+        SESSION_IDLE_DURATION,
+    )
+    .await?;
+
+    set_access_cookie(cookies, &state.jwt_secret, user_id, &jti)?;
+    set_refresh_cookie(cookies, refresh_token);
+    Ok(())
+}
+
+/// Finds the session the request belongs to.
+///
+/// The access token is checked against the sessions table so revoked sessions stop
+/// working immediately. If it has expired, the refresh token is swapped for a new
+/// one and a new access token is issued, which keeps active users logged in. A
+/// refresh token that was swapped moments ago, by another request sent at the same
+/// time, still gets a new access token, but leaves the new refresh token alone.
+///
+/// # Returns
+/// * `Option<(i64, i64)>` - The session id and its user id.
+///
+pub async fn authenticate_session(cookies: &Cookies, state: &AppState) -> Option<(i64, i64)> {
+    if let Some(claims) = access_token_claims(cookies, &state.jwt_secret) {
+        if let Ok(Some((session_id, user_id))) = use_session(&state.pool, &claims.jti).await {
+            if claims.sub == user_id.to_string() {
+                return Some((session_id, user_id));
+            }
+        }
+    }
+
+    let refresh_token_hash = hash_token(cookies.get(REFRESH_COOKIE)?.value());
+    let new_refresh_token = generate_token();
+    let refreshed = refresh_session(
+        &state.pool,
+        &refresh_token_hash,
+        &hash_token(&new_refresh_token),
+        SESSION_IDLE_DURATION,
+    )
+    .await
+    .ok()?;
+
+    let (session_id, jti, user_id) = match refreshed {
+        Some(session) => {
+            set_refresh_cookie(cookies, new_refresh_token);
+            session
+        }
+        None => {
+            use_previous_refresh_token(&state.pool, &refresh_token_hash, REFRESH_GRACE_DURATION)
+                .await
+                .ok()??
+        }
+    };
+
+    set_access_cookie(cookies, &state.jwt_secret, user_id, &jti).ok()?;
+    Some((session_id, user_id))
+}
+
+/// Removes the session cookies from the browser.
+pub fn clear_session_cookies(cookies: &Cookies) {
+    cookies.remove(Cookie::build(ACCESS_COOKIE).path("/").build());
+    cookies.remove(Cookie::build(REFRESH_COOKIE).path("/").build());
+}
+
+fn access_token_claims(cookies: &Cookies, jwt_secret: &str) -> Option<Claims> {
+    let token = cookies.get(ACCESS_COOKIE)?;
+    decode::<Claims>(
+        token.value(),
+        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &Validation::default(),
+    )
+    .ok()
+    .map(|data| data.claims)
+}
+
+fn set_access_cookie(
+    cookies: &Cookies,
+    jwt_secret: &str,
+    user_id: i64,
+    jti: &str,
+) -> Result<(), jsonwebtoken::errors::Error> {
+    let expiration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize
+        + ACCESS_TOKEN_DURATION as usize;
+
+    let claims = Claims {
+        sub: user_id.to_string(),
+        jti: jti.to_string(),
+        exp: expiration,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?;
+
+    let cookie = CookieBuilder::new(ACCESS_COOKIE, token)
+        .http_only(true) // Prevents JavaScript access
+        .secure(true) // Ensures the cookie is sent over HTTPS
+        .path("/") // Defines the cookie scope
+        .max_age(Duration::seconds(ACCESS_TOKEN_DURATION)) // Matches JWT expiration
+        .expires(OffsetDateTime::now_utc() + Duration::seconds(ACCESS_TOKEN_DURATION))
+        .build();
+    cookies.add(cookie);
+    Ok(())
+}
+
+fn set_refresh_cookie(cookies: &Cookies, refresh_token: String) {
+    let cookie = CookieBuilder::new(REFRESH_COOKIE, refresh_token)
+        .http_only(true)
+        .secure(true)
+        .path("/")
+        .max_age(Duration::seconds(SESSION_IDLE_DURATION))
+        .expires(OffsetDateTime::now_utc() + Duration::seconds(SESSION_IDLE_DURATION))
+        .build();
+    cookies.add(cookie);
+}
@@ -0,0 +1,145 @@
+use crate::types::Visibility;
+use crate::utils::hex_encode;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signed image URLs stay valid for between one and two of these periods. Rounding
+/// the expiry keeps URLs stable for a while so browsers can cache the images.
+const SIGNATURE_PERIOD: u64 = 3600;
+
+/// Derives the image URL signing key from the server secret, so a signature can't
+/// be reused as, or confused with, a JWT signed with the same secret.
+pub fn derive_signing_key(secret: &str) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"photo-gallery image urls");
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Returns the query string to append to the album's `/uploads` URLs.
+///
+/// Public albums don't need one. For other albums it carries an expiry and an HMAC
+/// over the album id and that expiry, which authorizes any image in the album.
+pub fn album_image_query(key: &[u8], album_id: i64, visibility: Visibility) -> String {
+    if visibility == Visibility::Public {
+        return String::new();
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let expires = (now / SIGNATURE_PERIOD + 2) * SIGNATURE_PERIOD;
+    let signature = hex_encode(&mac_for(key, album_id, expires).finalize().into_bytes());
+
+    format!("?expires={}&sig={}", expires, signature)
+}
+
+/// Checks an image URL signature, rejecting expired and malformed ones.
+pub fn verify_image_signature(key: &[u8], album_id: i64, expires: u64, signature: &str) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    if expires <= now {
+        return false;
+    }
+
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    // Constant-time comparison
+    mac_for(key, album_id, expires)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn mac_for(key: &[u8], album_id: i64, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(format!("{}:{}", album_id, expires).as_bytes());
+    mac
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a query made by `album_image_query` into its expiry and signature.
+    fn parse_query(query: &str) -> (u64, String) {
+        let query = query.strip_prefix("?expires=").expect("query starts with the expiry");
+        let (expires, signature) = query.split_once("&sig=").expect("query has a signature");
+        (expires.parse().unwrap(), signature.to_string())
+    }
+
+    fn sign(key: &[u8], album_id: i64, expires: u64) -> String {
+        hex_encode(&mac_for(key, album_id, expires).finalize().into_bytes())
+    }
+
+    #[test]
+    fn accepts_a_valid_signature() {
+        let key = derive_signing_key("secret");
+        for visibility in [Visibility::Private, Visibility::Unlisted, Visibility::Password] {
+            let (expires, signature) = parse_query(&album_image_query(&key, 7, visibility));
+            assert!(verify_image_signature(&key, 7, expires, &signature));
+        }
+    }
+
+    #[test]
+    fn rejects_another_album() {
+        let key = derive_signing_key("secret");
+        let (expires, signature) = parse_query(&album_image_query(&key, 7, Visibility::Private));
+        assert!(!verify_image_signature(&key, 8, expires, &signature));
+    }
+
+    #[test]
+    fn rejects_a_changed_expiry() {
+        let key = derive_signing_key("secret");
+        let (expires, signature) = parse_query(&album_image_query(&key, 7, Visibility::Private));
+        assert!(!verify_image_signature(&key, 7, expires + SIGNATURE_PERIOD, &signature));
+    }
+
+    #[test]
+    fn rejects_another_key() {
+        let key = derive_signing_key("secret");
+        let (expires, signature) = parse_query(&album_image_query(&key, 7, Visibility::Private));
+        assert!(!verify_image_signature(&derive_signing_key("other"), 7, expires, &signature));
+    }
+
+    #[test]
+    fn rejects_an_expired_link() {
+        let key = derive_signing_key("secret");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expires = now - 1;
+        assert!(!verify_image_signature(&key, 7, expires, &sign(&key, 7, expires)));
+    }
+
+    #[test]
+    fn rejects_a_malformed_signature() {
+        let key = derive_signing_key("secret");
+        let (expires, signature) = parse_query(&album_image_query(&key, 7, Visibility::Private));
+        assert!(!verify_image_signature(&key, 7, expires, &signature[1..]));
+        assert!(!verify_image_signature(&key, 7, expires, "not hex"));
+    }
+
+    #[test]
+    fn public_albums_need_no_query() {
+        let key = derive_signing_key("secret");
+        assert_eq!(album_image_query(&key, 7, Visibility::Public), "");
+    }
+}
@@ -0,0 +1,206 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::utils::hash_token;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Name shown next to the account in authenticator apps.
+const ISSUER: &str = "Photo Gallery";
+/// RFC 6238 defaults, which is what every authenticator app expects.
+const TIME_STEP: u64 = 30;
+const DIGITS: u32 = 6;
+/// Codes from one step either side of now are accepted, to allow for clock drift.
+const ALLOWED_DRIFT: i64 = 1;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a random 160-bit TOTP secret, base32-encoded.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    encode_base32(&bytes)
+}
+
+/// Returns the `otpauth://` URI authenticator apps import the secret from.
+pub fn otpauth_uri(username: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={TIME_STEP}",
+        issuer = percent_encode(ISSUER),
+        account = percent_encode(username),
+    )
+}
+
+/// Renders a URI as an inline SVG QR code.
+pub fn qr_code_svg(uri: &str) -> Option<String> {
+    let code = QrCode::new(uri.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .quiet_zone(true)
+            .build(),
+    )
+}
+
+/// Checks a code against a secret.
+///
+/// # Returns
+/// * `Option<i64>` - The time step the code belongs to, so it can be marked as used,
+///   or `None` if it doesn't match any step within the allowed drift.
+///
+pub fn verify_code(secret: &str, code: &str) -> Option<i64> {
+    let key = decode_base32(secret)?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let current = (now / TIME_STEP) as i64;
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .find(|&step| code_at(&key, step as u64) == code)
+}
+
+/// The HOTP value (RFC 4226) for a counter, which TOTP uses with the time step as counter.
+fn code_at(key: &[u8], counter: u64) -> u32 {
+    let mut mac = HmacSha1::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    value % 10u32.pow(DIGITS)
+}
+
+/// Generates a fresh set of single-use recovery codes, formatted like `abcde-fghij`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 7];
+            OsRng.fill_bytes(&mut bytes);
+            let code = encode_base32(&bytes).to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+/// Hashes a recovery code for storage with `hash_token`, ignoring case, spaces
+/// and dashes.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hash_token(&normalized)
+}
+
+fn encode_base32(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+fn decode_base32(value: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in value.trim_end_matches('=').chars() {
+        let index = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 test vectors from RFC 6238 appendix B, cut down to six digits.
+    #[test]
+    fn code_at_matches_rfc_6238() {
+        let key = b"12345678901234567890";
+        for (time, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(code_at(key, time / TIME_STEP), code, "at T = {}", time);
+        }
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "MY"),
+            (b"fo", "MZXQ"),
+            (b"foo", "MZXW6"),
+            (b"foob", "MZXW6YQ"),
+            (b"fooba", "MZXW6YTB"),
+            (b"foobar", "MZXW6YTBOI"),
+        ] {
+            assert_eq!(encode_base32(bytes), encoded);
+            assert_eq!(decode_base32(encoded).as_deref(), Some(bytes));
+        }
+    }
+
+    #[test]
+    fn base32_round_trips() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        for len in 0..=bytes.len() {
+            let encoded = encode_base32(&bytes[..len]);
+            assert_eq!(decode_base32(&encoded).as_deref(), Some(&bytes[..len]));
+        }
+        assert_eq!(decode_base32("mzxw6ytboi======").as_deref(), Some(&b"foobar"[..]));
+        assert_eq!(decode_base32("MZXW1"), None);
+    }
+}
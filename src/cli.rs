@@ -0,0 +1,504 @@
+use sqlx::SqlitePool;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use crate::archive::{export_archive, image_path, import_archive};
+use crate::auth::api_tokens::generate_api_token;
+use crate::auth::password::hash_password;
+use crate::config::Config;
+use crate::db::{self, disable_two_factor, find_user_by_username};
+use crate::handlers::users::MIN_PASSWORD_LENGTH;
+use crate::import::import_directory;
+use crate::state::{run_migrations, MIGRATOR};
+use crate::static_site::export_static_site;
+use crate::types::{ApiScope, Role, User};
+use crate::utils::{extract_image_description, generate_token, hash_token, ImageQuality};
+
+const USAGE: &str = "Usage:
+  photo-gallery                  Start the web server
+  photo-gallery import <dir>     Import each subdirectory of <dir> as an album
+  photo-gallery export <file.zip> [album-id]
+                                 Export an album (or every album) with a manifest
+  photo-gallery import-archive <file.zip>
+                                 Recreate the albums in an exported archive
+  photo-gallery export-static <outdir>
+                                 Render the gallery as a self-contained static site
+  photo-gallery reset-2fa <username>
+                                 Turn off two-factor authentication for a user
+                                 who has lost their authenticator and recovery codes
+  photo-gallery users            List users
+  photo-gallery create-user <username> <viewer|editor|owner>
+                                 Create a user, asking for their password
+  photo-gallery reset-password <username>
+                                 Set a new password for a user, asking for it, and
+                                 log them out everywhere
+  photo-gallery disable-user <username>
+                                 Stop a user from signing in and log them out
+  photo-gallery enable-user <username>
+                                 Let a disabled user sign in again
+  photo-gallery hash-password    Print the hash of a password, for ADMIN_PASSWORD
+  photo-gallery create-token <username> <name> <scope,...> [days]
+                                 Create an API token for a user, e.g. with scopes
+                                 albums:read,albums:write
+  photo-gallery rotate-jwt-secret [env-file]
+                                 Write a new JWT_SECRET to env-file (default .env)
+                                 and log everyone out
+  photo-gallery reindex          Read titles, captions and keywords from the images
+                                 again, tag the images with their keywords and
+                                 rebuild the search index
+  photo-gallery migrate          Apply any pending database migrations
+  photo-gallery stats            Show how many albums, images and users there are";
+
+/// Runs a command-line subcommand against the database instead of starting the server.
+pub async fn run(config: &Config, pool: SqlitePool, args: &[String]) {
+    // Every other command expects an up to date database
+    if args.first().is_some_and(|command| command != "migrate") {
+        if let Err(e) = run_migrations(&pool).await {
+            eprintln!("Failed to run migrations: {}", e);
+            process::exit(1);
+        }
+    }
+
+    match args {
+        [command, dir] if command == "import" => {
+            let root = Path::new(dir);
+            if !root.is_dir() {
+                eprintln!("'{}' is not a directory", dir);
+                process::exit(1);
+            }
+
+            match import_directory(&pool, &config.images, root).await {
+                Ok(summary) => {
+                    println!();
+                    println!("Import complete:");
+                    println!("  Albums created:  {}", summary.albums_created);
+                    println!("  Albums updated:  {}", summary.albums_reused);
+                    println!("  Images imported: {}", summary.images_imported);
+                    println!("  Images skipped:  {} (already imported)", summary.images_skipped);
+                    println!("  Images failed:   {}", summary.images_failed);
+                    if summary.images_failed > 0 {
+                        process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Import failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, out, rest @ ..] if command == "export" && rest.len() <= 1 => {
+            let album_id = match rest.first().map(|id| id.parse::<i64>()) {
+                Some(Ok(id)) => Some(id),
+                Some(Err(_)) => {
+                    eprintln!("Album id must be a number");
+                    process::exit(2);
+                }
+                None => None,
+            };
+
+            match export_archive(&pool, album_id, Path::new(out)).await {
+                Ok(manifest) => println!(
+                    "Exported {} album(s) and {} image(s) to {}",
+                    manifest.albums.len(),
+                    manifest.images.len(),
+                    out
+                ),
+                Err(e) => {
+                    eprintln!("Export failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, file] if command == "import-archive" => {
+            match import_archive(&pool, &config.images, Path::new(file)).await {
+                Ok(summary) => {
+                    println!("Archive import complete:");
+                    println!("  Albums:          {}", summary.albums);
+                    println!("  Images imported: {}", summary.images_imported);
+                    println!("  Images skipped:  {} (already imported)", summary.images_skipped);
+                    println!("  Images failed:   {}", summary.images_failed);
+                    if summary.images_failed > 0 {
+                        process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Archive import failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, out] if command == "export-static" => {
+            match export_static_site(&pool, config, Path::new(out)).await {
+                Ok(summary) => println!(
+                    "Exported {} page(s) and {} image(s) to {}",
+                    summary.pages, summary.images, out
+                ),
+                Err(e) => {
+                    eprintln!("Static export failed: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, username] if command == "reset-2fa" => {
+            let user = find_user(&pool, username).await;
+            match disable_two_factor(&pool, user.id).await {
+                Ok(()) => println!(
+                    "Two-factor authentication turned off for {}. They can sign in with their password and set it up again.",
+                    user.username
+                ),
+                Err(e) => {
+                    eprintln!("Failed to reset two-factor authentication: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command] if command == "users" => {
+            let users = match db::get_users(&pool).await {
+                Ok(users) => users,
+                Err(e) => {
+                    eprintln!("Failed to list users: {}", e);
+                    process::exit(1);
+                }
+            };
+            println!("{:<5} {:<24} {:<7} CREATED", "ID", "USERNAME", "ROLE");
+            for user in users {
+                println!(
+                    "{:<5} {:<24} {:<7} {}{}",
+                    user.id,
+                    user.username,
+                    user.role.as_str(),
+                    user.created_at,
+                    if user.disabled { " disabled" } else { "" }
+                );
+            }
+        }
+        [command, username, role] if command == "create-user" => {
+            let Some(role) = Role::parse(role) else {
+                eprintln!("Role must be viewer, editor or owner");
+                process::exit(2);
+            };
+            let password_hash = prompt_new_password();
+
+            match db::create_user(&pool, username.trim(), &password_hash, role).await {
+                Ok(id) => println!("Created {} {} (id {})", role.as_str(), username.trim(), id),
+                Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                    eprintln!("The username '{}' is already taken", username.trim());
+                    process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Failed to create user: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, username] if command == "reset-password" => {
+            let user = find_user(&pool, username).await;
+            let password_hash = prompt_new_password();
+
+            let result = match db::update_user_password(&pool, user.id, &password_hash).await {
+                Ok(()) => db::revoke_user_sessions(&pool, user.id).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => println!(
+                    "Password changed for {}. They have been logged out everywhere.",
+                    user.username
+                ),
+                Err(e) => {
+                    eprintln!("Failed to change password: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, username] if command == "disable-user" => {
+            let user = find_user(&pool, username).await;
+            if user.role == Role::Owner && !user.disabled {
+                match db::count_owners(&pool).await {
+                    Ok(count) if count > 1 => {}
+                    Ok(_) => {
+                        eprintln!("{} is the only owner who can sign in", user.username);
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to count owners: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+
+            let result = match db::set_user_disabled(&pool, user.id, true).await {
+                Ok(()) => db::revoke_user_sessions(&pool, user.id).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => println!(
+                    "Disabled {}. They have been logged out and their API tokens stop working.",
+                    user.username
+                ),
+                Err(e) => {
+                    eprintln!("Failed to disable user: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, username] if command == "enable-user" => {
+            let user = find_user(&pool, username).await;
+            match db::set_user_disabled(&pool, user.id, false).await {
+                Ok(()) => println!("Enabled {}", user.username),
+                Err(e) => {
+                    eprintln!("Failed to enable user: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command] if command == "hash-password" => {
+            let password_hash = prompt_new_password();
+            // Escape the `$` characters in the hash for use in .env files
+            println!("Hashed password: {}", password_hash.replace('$', "\\$"));
+        }
+        [command, username, name, scopes, rest @ ..]
+            if command == "create-token" && rest.len() <= 1 =>
+        {
+            let user = find_user(&pool, username).await;
+            let mut parsed_scopes = Vec::new();
+            for scope in scopes.split(',').map(str::trim) {
+                let Some(scope) = ApiScope::parse(scope) else {
+                    eprintln!(
+                        "Unknown scope '{}'; use albums:read, albums:write or images:delete",
+                        scope
+                    );
+                    process::exit(2);
+                };
+                parsed_scopes.push(scope.as_str());
+            }
+            parsed_scopes.sort_unstable();
+            parsed_scopes.dedup();
+            let expires_in_days = match rest.first().map(|days| days.parse::<i64>()) {
+                Some(Ok(days)) if days > 0 => Some(days),
+                Some(_) => {
+                    eprintln!("Days must be a positive number");
+                    process::exit(2);
+                }
+                None => None,
+            };
+
+            let token = generate_api_token();
+            match db::create_api_token(
+                &pool,
+                user.id,
+                name,
+                &hash_token(&token),
+                &parsed_scopes.join(" "),
+                expires_in_days,
+            )
+            .await
+            {
+                Ok(_) => {
+                    println!("API token for {} (shown only once):", user.username);
+                    println!("{}", token);
+                }
+                Err(e) => {
+                    eprintln!("Failed to create API token: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        [command, rest @ ..] if command == "rotate-jwt-secret" && rest.len() <= 1 => {
+            let env_file = rest.first().map(String::as_str).unwrap_or(".env");
+            let secret = generate_token();
+
+            match set_env_var(Path::new(env_file), "JWT_SECRET", &secret) {
+                Ok(true) => println!("Wrote a new JWT_SECRET to {}", env_file),
+                Ok(false) => {
+                    println!("{} has no JWT_SECRET; set it to:", env_file);
+                    println!("{}", secret);
+                }
+                Err(e) => {
+                    eprintln!("Failed to update {} ({}); set JWT_SECRET to:", env_file, e);
+                    println!("{}", secret);
+                }
+            }
+
+            match db::revoke_all_sessions(&pool).await {
+                Ok(count) => println!("Logged out {} session(s).", count),
+                Err(e) => {
+                    eprintln!("Failed to log out sessions: {}", e);
+                    process::exit(1);
+                }
+            }
+            println!(
+                "Restart the server to use the new secret. Signed image links and unlocked albums stop working too."
+            );
+        }
+        [command] if command == "reindex" => {
+            let album_ids = match db::get_album_ids(&pool).await {
+                Ok(ids) => ids,
+                Err(e) => {
+                    eprintln!("Failed to list albums: {}", e);
+                    process::exit(1);
+                }
+            };
+
+            let mut updated = 0;
+            let mut failed = 0;
+            for album_id in album_ids {
+                let images = match db::get_album_with_images(&pool, album_id).await {
+                    Ok((_, images)) => images,
+                    Err(e) => {
+                        eprintln!("Failed to read album {}: {}", album_id, e);
+                        process::exit(1);
+                    }
+                };
+                for image in images {
+                    let path = image_path(album_id, ImageQuality::Full, &image.filename);
+                    let data = match fs::read(&path) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            eprintln!("Skipping {}: {}", path.display(), e);
+                            failed += 1;
+                            continue;
+                        }
+                    };
+                    let description = extract_image_description(&data);
+                    let result = db::update_image_description(&pool, image.id, &description).await;
+                    if let Err(e) = result {
+                        eprintln!("Failed to update image {}: {}", image.id, e);
+                        process::exit(1);
+                    }
+                    updated += 1;
+                }
+            }
+
+            if let Err(e) = db::rebuild_search_index(&pool).await {
+                eprintln!("Failed to rebuild the search index: {}", e);
+                process::exit(1);
+            }
+            println!("Reindexed {} image(s)", updated);
+            if failed > 0 {
+                println!("{} image file(s) couldn't be read", failed);
+                process::exit(1);
+            }
+        }
+        [command] if command == "migrate" => {
+            // Fails on a new database, which has no migrations table yet
+            let applied = db::get_applied_migrations(&pool)
+                .await
+                .unwrap_or_default();
+            if let Err(e) = run_migrations(&pool).await {
+                eprintln!("Migration failed: {}", e);
+                process::exit(1);
+            }
+
+            let mut count = 0;
+            for migration in MIGRATOR.iter().filter(|m| !applied.contains(&m.version)) {
+                println!("Applied {} {}", migration.version, migration.description);
+                count += 1;
+            }
+            if count == 0 {
+                println!("The database is up to date");
+            }
+        }
+        [command] if command == "stats" => {
+            let stats = match db::get_site_stats(&pool).await {
+                Ok(stats) => stats,
+                Err(e) => {
+                    eprintln!("Failed to read stats: {}", e);
+                    process::exit(1);
+                }
+            };
+            let users = db::count_users(&pool).await.unwrap_or(0);
+            let (albums, images, storage) = stats;
+            println!("Albums:  {}", albums);
+            println!("Images:  {}", images);
+            println!("Storage: {}", format_size(storage));
+            println!("Users:   {}", users);
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Looks up a user by username, exiting if there is no such user.
+async fn find_user(pool: &SqlitePool, username: &str) -> User {
+    match find_user_by_username(pool, username).await {
+        Ok(Some((user, _))) => user,
+        Ok(None) => {
+            eprintln!("No user named '{}'", username);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to look up user: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Asks for a new password twice without echoing it, and returns its hash.
+fn prompt_new_password() -> String {
+    let read = |prompt: &str| {
+        rpassword::prompt_password(prompt).unwrap_or_else(|e| {
+            eprintln!("Failed to read password: {}", e);
+            process::exit(1);
+        })
+    };
+
+    let password = read("New password: ");
+    if password.len() < MIN_PASSWORD_LENGTH {
+        eprintln!("Password must be at least {} characters", MIN_PASSWORD_LENGTH);
+        process::exit(1);
+    }
+    if read("Repeat the password: ") != password {
+        eprintln!("Passwords don't match");
+        process::exit(1);
+    }
+
+    hash_password(&password).unwrap_or_else(|e| {
+        eprintln!("Failed to hash password: {}", e);
+        process::exit(1);
+    })
+}
+
+/// Replaces the value of `key` in an env file, keeping everything else.
+///
+/// # Returns
+/// * `io::Result<bool>` - Whether the file had the key.
+///
+fn set_env_var(path: &Path, key: &str, value: &str) -> io::Result<bool> {
+    let contents = fs::read_to_string(path)?;
+    let prefix = format!("{}=", key);
+
+    let mut found = false;
+    let lines: Vec<String> = contents
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with(&prefix) {
+                found = true;
+                format!("{}{}", prefix, value)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if found {
+        fs::write(path, lines.join("\n") + "\n")?;
+    }
+    Ok(found)
+}
+
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
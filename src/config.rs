@@ -0,0 +1,359 @@
+use serde::Deserialize;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// File the configuration is read from unless `CONFIG_FILE` names another.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Everything the server can be configured with.
+///
+/// Settings are read from a TOML file, then environment variables (and `.env`)
+/// override them, so existing deployments configured only through the environment
+/// keep working. Anything left unset has a default, except `auth.jwt_secret` and
+/// `database.url`, which have to be given.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub theme: ThemeConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub oidc: OidcSettings,
+    pub limits: LimitsConfig,
+    pub images: ImageConfig,
+    pub inbox: InboxConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// `development` or `production`, available to templates as `app_env`
+    pub app_env: String,
+    /// Debug builds only: 0 doesn't reload templates, 1 reloads them when their
+    /// files change, 2 also watches the templates directory for new ones
+    pub auto_reload_mode: u8,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "127.0.0.1".to_string(),
+            port: 3000,
+            app_env: "production".to_string(),
+            auto_reload_mode: 0,
+        }
+    }
+}
+
+/// Which theme's templates override the built-in ones. A theme is a directory of
+/// templates under `dir`; any template it doesn't have falls back to the default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub dir: PathBuf,
+    /// The built-in templates are used as they are if unset
+    pub name: Option<String>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            dir: PathBuf::from("themes"),
+            name: None,
+        }
+    }
+}
+
+impl ThemeConfig {
+    /// The directory of the selected theme, if there is one.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.name.as_ref().map(|name| self.dir.join(name))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            max_connections: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    /// Only used to create the first owner when there are no users yet
+    pub admin_username: Option<String>,
+    /// Argon2 hash of the first owner's password
+    pub admin_password: Option<String>,
+}
+
+/// Single sign-on settings. It is turned on by setting `issuer`, which then needs
+/// `client_id` and `redirect_url` as well.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OidcSettings {
+    pub issuer: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_url: Option<String>,
+    /// Shown on the login button
+    pub provider_name: String,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        OidcSettings {
+            issuer: None,
+            client_id: None,
+            client_secret: None,
+            redirect_url: None,
+            provider_name: "single sign-on".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Requests each client may make per second once its burst is used up
+    pub requests_per_second: u64,
+    pub request_burst: u32,
+    /// Largest request body accepted, in megabytes
+    pub max_request_mb: usize,
+    /// Largest upload form accepted, in megabytes
+    pub max_upload_mb: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            requests_per_second: 5,
+            request_burst: 10,
+            max_request_mb: 2000,
+            max_upload_mb: 1024,
+        }
+    }
+}
+
+/// Sizes and JPEG qualities of the derivatives made for every uploaded image.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageConfig {
+    /// Longest side of the optimized image, in pixels
+    pub optimized_max_size: u32,
+    pub optimized_quality: i32,
+    /// Longest side of the thumbnail, in pixels
+    pub thumbnail_max_size: u32,
+    pub thumbnail_quality: i32,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig {
+            optimized_max_size: 1920,
+            optimized_quality: 85,
+            thumbnail_max_size: 400,
+            thumbnail_quality: 95,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InboxConfig {
+    /// Drop folder to watch for new images; not watched if unset
+    pub dir: Option<PathBuf>,
+    /// Where imported files are moved to
+    pub archive_dir: PathBuf,
+}
+
+impl Default for InboxConfig {
+    fn default() -> Self {
+        InboxConfig {
+            dir: None,
+            archive_dir: PathBuf::from("archive"),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `CONFIG_FILE` (default `config.toml`, which may
+    /// be missing), applies the environment overrides and validates the result.
+    ///
+    /// # Returns
+    /// * `Result<Config, String>` - The configuration, or every problem found with it.
+    ///
+    pub fn load() -> Result<Config, String> {
+        let mut config = match env::var("CONFIG_FILE") {
+            Ok(path) => Config::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            Err(_) => Config::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Overrides settings with the environment variables the server has always read.
+    fn apply_env(&mut self) -> Result<(), String> {
+        override_string("HOST", &mut self.server.host);
+        override_parsed("PORT", &mut self.server.port)?;
+        override_string("APP_ENV", &mut self.server.app_env);
+        override_parsed("AUTO_RELOAD_MODE", &mut self.server.auto_reload_mode)?;
+
+        override_optional("THEME", &mut self.theme.name);
+        if let Ok(dir) = env::var("THEMES_DIR") {
+            self.theme.dir = dir.into();
+        }
+
+        override_string("DATABASE_URL", &mut self.database.url);
+
+        override_string("JWT_SECRET", &mut self.auth.jwt_secret);
+        override_optional("ADMIN_USERNAME", &mut self.auth.admin_username);
+        override_optional("ADMIN_PASSWORD", &mut self.auth.admin_password);
+
+        override_optional("OIDC_ISSUER", &mut self.oidc.issuer);
+        override_optional("OIDC_CLIENT_ID", &mut self.oidc.client_id);
+        override_optional("OIDC_CLIENT_SECRET", &mut self.oidc.client_secret);
+        override_optional("OIDC_REDIRECT_URL", &mut self.oidc.redirect_url);
+        override_string("OIDC_PROVIDER_NAME", &mut self.oidc.provider_name);
+
+        if let Ok(dir) = env::var("INBOX_DIR") {
+            self.inbox.dir = Some(dir.into());
+        }
+        if let Ok(dir) = env::var("ARCHIVE_DIR") {
+            self.inbox.archive_dir = dir.into();
+        }
+        Ok(())
+    }
+
+    fn validate(&mut self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.database.url.is_empty() {
+            problems.push("database.url (DATABASE_URL) must be set".to_string());
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".to_string());
+        }
+        if self.auth.jwt_secret.is_empty() {
+            problems.push("auth.jwt_secret (JWT_SECRET) must be set".to_string());
+        }
+        if self.server.auto_reload_mode > 2 {
+            problems.push("server.auto_reload_mode must be 0, 1 or 2".to_string());
+        }
+
+        // Blank values in .env mean "not set"
+        for value in [
+            &mut self.theme.name,
+            &mut self.oidc.issuer,
+            &mut self.oidc.client_id,
+            &mut self.oidc.client_secret,
+            &mut self.oidc.redirect_url,
+        ] {
+            if value.as_deref().is_some_and(|value| value.trim().is_empty()) {
+                *value = None;
+            }
+        }
+
+        if let Some(name) = &self.theme.name {
+            let path = self.theme.dir.join(name);
+            if name.contains(['/', '\\']) || name.starts_with('.') {
+                problems.push(format!("theme.name (THEME) '{}' must be a plain name", name));
+            } else if !path.is_dir() {
+                problems.push(format!(
+                    "theme.name (THEME) is '{}' but {} isn't a directory",
+                    name,
+                    path.display()
+                ));
+            }
+        }
+        if self.oidc.issuer.is_some() {
+            if self.oidc.client_id.is_none() {
+                problems.push("oidc.client_id (OIDC_CLIENT_ID) must be set with an issuer".into());
+            }
+            if self.oidc.redirect_url.is_none() {
+                problems.push(
+                    "oidc.redirect_url (OIDC_REDIRECT_URL) must be set with an issuer".into(),
+                );
+            }
+        }
+
+        if self.limits.requests_per_second == 0 || self.limits.request_burst == 0 {
+            problems.push("limits.requests_per_second and request_burst must be at least 1".into());
+        }
+        if self.limits.max_request_mb == 0 || self.limits.max_upload_mb == 0 {
+            problems.push("limits.max_request_mb and max_upload_mb must be at least 1".into());
+        }
+
+        let images = &self.images;
+        if images.optimized_max_size == 0 || images.thumbnail_max_size == 0 {
+            problems.push(
+                "images.optimized_max_size and thumbnail_max_size must be at least 1".into(),
+            );
+        }
+        for (name, quality) in [
+            ("images.optimized_quality", images.optimized_quality),
+            ("images.thumbnail_quality", images.thumbnail_quality),
+        ] {
+            if !(1..=100).contains(&quality) {
+                problems.push(format!("{} must be between 1 and 100, not {}", name, quality));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+}
+
+fn override_string(name: &str, value: &mut String) {
+    if let Ok(var) = env::var(name) {
+        *value = var;
+    }
+}
+
+fn override_optional(name: &str, value: &mut Option<String>) {
+    if let Ok(var) = env::var(name) {
+        *value = Some(var);
+    }
+}
+
+fn override_parsed<T>(name: &str, value: &mut T) -> Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(var) = env::var(name) {
+        *value = var
+            .trim()
+            .parse()
+            .map_err(|e| format!("{} must be a number, not '{}' ({})", name, var, e))?;
+    }
+    Ok(())
+}
@@ -1,29 +1,47 @@
-use crate::types::{Album, CreateAlbumRequest, Image};
+use crate::types::{
+    Album, AlbumGrant, AlbumPermission, AlbumSearchHit, ApiToken, CreateAlbumRequest,
+    CreateShareLinkRequest, FilteredImage, Image, ImageDescription, ImageFilter, ImageSearchHit, ContactLink, LoginEvent, LoginOutcome, OidcIdentity, OidcIdentityKind, Role, Session,
+    ShareLink, SiteSettings, SmartAlbum, SmartAlbumRequest, SmartAlbumSummary, Tag, TaggedAlbum,
+    TaggedImage, TwoFactor, User, Visibility,
+};
+use crate::utils::tag_slug;
 use sqlx::SqlitePool;
+use std::collections::HashMap;
 
+/// Creates an album owned by `owner_id`, or by nobody (site owners only) if `None`.
 pub async fn create_album(
     pool: &SqlitePool,
     album: &CreateAlbumRequest,
+    owner_id: Option<i64>,
 ) -> Result<i64, sqlx::Error> {
+    let visibility = album.visibility.as_str();
     let result = sqlx::query!(
         r#"
-        INSERT INTO albums (name, description, date, num_images)
-        VALUES (?, ?, ?, 0)
+        INSERT INTO albums (name, description, date, num_images, allow_download, visibility, owner_id)
+        VALUES (?, ?, ?, 0, ?, ?, ?)
         "#,
         album.name,
         album.description,
         album.date,
+        album.allow_download,
+        visibility,
+        owner_id,
     )
     .execute(pool)
     .await?;
 
-    Ok(result.last_insert_rowid())
+    let album_id = result.last_insert_rowid();
+    index_album(pool, album_id).await?;
+    Ok(album_id)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_image(
     pool: &SqlitePool,
     album_id: i64,
     filename: &str,
+    original_filename: &str,
+    checksum: &str,
     file_size: i64,
     camera_make: &str,
     camera_model: &str,
@@ -34,19 +52,23 @@ pub async fn create_image(
     focal_length: &str,
     light_source: &str,
     date_created: &str,
+    description: &ImageDescription,
 ) -> Result<i64, sqlx::Error> {
+    let keywords = Some(description.keywords.join(", ")).filter(|k| !k.is_empty());
     let result = sqlx::query!(
         r#"
         INSERT INTO images (
-            album_id, filename, file_size, 
+            album_id, filename, original_filename, checksum, file_size, 
             camera_make, camera_model, lens_model, 
             iso, aperture, shutter_speed, focal_length, 
-            light_source, date_created
+            light_source, date_created, title, caption, keywords
         )
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         album_id,
         filename,
+        original_filename,
+        checksum,
         file_size,
         camera_make,
         camera_model,
@@ -57,6 +79,9 @@ pub async fn create_image(
         focal_length,
         light_source,
         date_created,
+        description.title,
+        description.caption,
+        keywords,
     )
     .execute(pool)
     .await?;
@@ -69,7 +94,52 @@ pub async fn create_image(
     .execute(pool)
     .await?;
 
-    Ok(result.last_insert_rowid())
+    let image_id = result.last_insert_rowid();
+    add_keyword_tags(pool, image_id, &description.keywords).await?;
+    index_image(pool, image_id).await?;
+    Ok(image_id)
+}
+
+/// Finds an album by its exact name, returning the most recently created match.
+pub async fn find_album_by_name(pool: &SqlitePool, name: &str) -> Result<Option<i64>, sqlx::Error> {
+    let album_id = sqlx::query_scalar!(
+        "SELECT id FROM albums WHERE name = ? ORDER BY id DESC LIMIT 1",
+        name
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(album_id)
+}
+
+/// Returns the checksums of all images already stored in an album.
+pub async fn get_album_checksums(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let checksums = sqlx::query_scalar!(
+        r#"SELECT checksum as "checksum!" FROM images WHERE album_id = ? AND checksum IS NOT NULL"#,
+        album_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(checksums)
+}
+
+/// Maps the checksums of an album's images to their ids.
+pub async fn get_album_image_ids_by_checksum(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<HashMap<String, i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id as "id!", checksum as "checksum!" FROM images WHERE album_id = ? AND checksum IS NOT NULL"#,
+        album_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.checksum, row.id)).collect())
 }
 
 pub async fn update_album_metadata(pool: &SqlitePool, album_id: i64) -> Result<(), sqlx::Error> {
@@ -113,12 +183,18 @@ pub async fn update_album_metadata(pool: &SqlitePool, album_id: i64) -> Result<(
     .execute(pool)
     .await?;
 
+    // The album's camera and lens are searchable
+    index_album(pool, album_id).await?;
     Ok(())
 }
 
 
+/// Lists albums newest first with their cover image and size on disk.
+///
+/// Only public albums are returned unless `include_hidden` is set.
 pub async fn get_albums_with_oldest_image(
     pool: &SqlitePool,
+    include_hidden: bool,
 ) -> Result<Vec<(Album, Option<String>, i64)>, sqlx::Error> {
     #[derive(sqlx::FromRow)]
     struct AlbumWithImage {
@@ -130,6 +206,8 @@ pub async fn get_albums_with_oldest_image(
         camera_model: Option<String>,
         lens_model: Option<String>,
         aperture: Option<String>,
+        allow_download: bool,
+        visibility: Visibility,
         oldest_image: Option<String>,
     }
 
@@ -145,6 +223,8 @@ pub async fn get_albums_with_oldest_image(
             a.camera_model, 
             a.lens_model, 
             a.aperture,
+            a.allow_download,
+            a.visibility as "visibility: Visibility",
             (
                 SELECT i.filename
                 FROM images i
@@ -153,8 +233,10 @@ pub async fn get_albums_with_oldest_image(
                 LIMIT 1
             ) as oldest_image
         FROM albums a
+        WHERE ? OR a.visibility = 'public'
         ORDER BY a.date DESC
         "#,
+        include_hidden,
     )
     .fetch_all(pool)
     .await?;
@@ -172,6 +254,8 @@ pub async fn get_albums_with_oldest_image(
                 camera_model: result.camera_model,
                 lens_model: result.lens_model,
                 aperture: result.aperture,
+                allow_download: result.allow_download,
+                visibility: result.visibility,
             },
             result.oldest_image,
             album_size,
@@ -188,7 +272,8 @@ pub async fn get_album_with_images(
     // Get the album
     let album_row = sqlx::query!(
         r#"
-        SELECT id, name, description, date, num_images, camera_model, lens_model, aperture
+        SELECT id, name, description, date, num_images, camera_model, lens_model, aperture,
+            allow_download, visibility as "visibility: Visibility"
         FROM albums
         WHERE id = ?
         "#,
@@ -206,15 +291,18 @@ pub async fn get_album_with_images(
         camera_model: album_row.camera_model,
         lens_model: album_row.lens_model,
         aperture: album_row.aperture,
+        allow_download: album_row.allow_download,
+        visibility: album_row.visibility,
     };
 
     // Get all images for this album, ordered by date_created (oldest first)
     let image_rows = sqlx::query!(
         r#"
         SELECT 
-            id, album_id, filename, camera_make, camera_model, 
-            lens_model, iso, aperture, shutter_speed, focal_length,
-            light_source, date_created, file_size
+            id, album_id, filename, original_filename, checksum,
+            camera_make, camera_model, lens_model, iso, aperture,
+            shutter_speed, focal_length, light_source, date_created, file_size,
+            title, caption, keywords, rating
         FROM images
         WHERE album_id = ?
         ORDER BY date_created ASC
@@ -230,6 +318,8 @@ pub async fn get_album_with_images(
             id: row.id,
             album_id: row.album_id,
             filename: row.filename,
+            original_filename: row.original_filename,
+            checksum: row.checksum,
             camera_make: row.camera_make,
             camera_model: row.camera_model,
             lens_model: row.lens_model,
@@ -240,12 +330,24 @@ pub async fn get_album_with_images(
             light_source: row.light_source,
             date_created: row.date_created,
             file_size: row.file_size.unwrap_or(0),
+            title: row.title,
+            caption: row.caption,
+            keywords: row.keywords,
+            rating: row.rating,
         })
         .collect();
 
     Ok((album, images))
 }
 
+pub async fn get_album_ids(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let ids = sqlx::query_scalar!("SELECT id FROM albums ORDER BY date ASC")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(ids)
+}
+
 pub async fn get_site_stats(pool: &SqlitePool) -> Result<(i64, i64, i64), sqlx::Error> {
     #[derive(sqlx::FromRow)]
     struct SiteStats {
@@ -289,23 +391,55 @@ pub async fn get_album_size(pool: &SqlitePool, album_id: i64) -> Result<i64, sql
 }
 
 pub async fn delete_album(pool: &SqlitePool, album_id: i64) -> Result<(), sqlx::Error> {
-    // First delete associated images
+    // First delete associated images and share links
+    sqlx::query!(
+        "DELETE FROM image_search WHERE rowid IN (SELECT id FROM images WHERE album_id = ?)",
+        album_id
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM image_tags WHERE image_id IN (SELECT id FROM images WHERE album_id = ?)",
+        album_id
+    )
+    .execute(pool)
+    .await?;
     sqlx::query!("DELETE FROM images WHERE album_id = ?", album_id)
         .execute(pool)
         .await?;
+    sqlx::query!("DELETE FROM album_tags WHERE album_id = ?", album_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM share_links WHERE album_id = ?", album_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM album_permissions WHERE album_id = ?", album_id)
+        .execute(pool)
+        .await?;
         
     // Then delete the album
     sqlx::query!("DELETE FROM albums WHERE id = ?", album_id)
         .execute(pool)
         .await?;
+    sqlx::query!("DELETE FROM album_search WHERE rowid = ?", album_id)
+        .execute(pool)
+        .await?;
+    delete_unused_tags(pool).await?;
         
     Ok(())
 }
 
 pub async fn delete_image(pool: &SqlitePool, image_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM image_tags WHERE image_id = ?", image_id)
+        .execute(pool)
+        .await?;
     sqlx::query!("DELETE FROM images WHERE id = ?", image_id)
         .execute(pool)
         .await?;
+    sqlx::query!("DELETE FROM image_search WHERE rowid = ?", image_id)
+        .execute(pool)
+        .await?;
+    delete_unused_tags(pool).await?;
     Ok(())
 }
 
@@ -313,9 +447,10 @@ pub async fn get_image(pool: &SqlitePool, image_id: i64) -> Result<Option<Image>
     let result = sqlx::query!(
         r#"
         SELECT 
-            id, album_id, filename, camera_make, camera_model, 
-            lens_model, iso, aperture, shutter_speed, focal_length,
-            light_source, date_created, file_size
+            id, album_id, filename, original_filename, checksum,
+            camera_make, camera_model, lens_model, iso, aperture,
+            shutter_speed, focal_length, light_source, date_created, file_size,
+            title, caption, keywords, rating
         FROM images
         WHERE id = ?
         "#,
@@ -329,6 +464,8 @@ pub async fn get_image(pool: &SqlitePool, image_id: i64) -> Result<Option<Image>
             id: row.id,
             album_id: row.album_id,
             filename: row.filename,
+            original_filename: row.original_filename,
+            checksum: row.checksum,
             camera_make: row.camera_make,
             camera_model: row.camera_model,
             lens_model: row.lens_model,
@@ -339,6 +476,10 @@ pub async fn get_image(pool: &SqlitePool, image_id: i64) -> Result<Option<Image>
             light_source: row.light_source,
             date_created: row.date_created,
             file_size: row.file_size.unwrap_or(0),
+            title: row.title,
+            caption: row.caption,
+            keywords: row.keywords,
+            rating: row.rating,
         }))
     } else {
         Ok(None)
@@ -351,16 +492,2010 @@ pub async fn update_album_details(
     name: &str,
     description: &Option<String>,
     date: &str,
+    allow_download: Option<bool>,
+    visibility: Option<Visibility>,
 ) -> Result<(), sqlx::Error> {
+    // Settings that aren't given keep their stored values
+    let visibility = visibility.map(|visibility| visibility.as_str());
     sqlx::query!(
-        "UPDATE albums SET name = $1, description = $2, date = $3 WHERE id = $4",
+        "UPDATE albums SET name = $1, description = $2, date = $3, allow_download = coalesce($4, allow_download), visibility = coalesce($5, visibility) WHERE id = $6",
         name,
         description,
         date,
+        allow_download,
+        visibility,
+        album_id
+    )
+    .execute(pool)
+    .await?;
+
+    index_album(pool, album_id).await?;
+    Ok(())
+}
+/// Returns an album's visibility, or `None` if it doesn't exist.
+pub async fn get_album_visibility(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Option<Visibility>, sqlx::Error> {
+    let visibility = sqlx::query_scalar!(
+        r#"SELECT visibility as "visibility: Visibility" FROM albums WHERE id = ?"#,
+        album_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(visibility)
+}
+
+pub async fn get_album_password_hash(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let hash = sqlx::query_scalar!("SELECT password_hash FROM albums WHERE id = ?", album_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(hash.flatten())
+}
+
+/// Sets or clears the argon2 hash that unlocks a password-protected album.
+pub async fn set_album_password_hash(
+    pool: &SqlitePool,
+    album_id: i64,
+    password_hash: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE albums SET password_hash = ? WHERE id = ?",
+        password_hash,
+        album_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn create_share_link(
+    pool: &SqlitePool,
+    album_id: i64,
+    token_hash: &str,
+    link: &CreateShareLinkRequest,
+) -> Result<i64, sqlx::Error> {
+    let expires_modifier = link.expires_in_days.map(|days| format!("+{} days", days));
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO share_links (album_id, token_hash, expires_at, allow_download, max_views)
+        VALUES (?, ?, CASE WHEN ? IS NULL THEN NULL ELSE datetime('now', ?) END, ?, ?)
+        "#,
+        album_id,
+        token_hash,
+        expires_modifier,
+        expires_modifier,
+        link.allow_download,
+        link.max_views,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_share_links(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Vec<ShareLink>, sqlx::Error> {
+    let links = sqlx::query_as!(
+        ShareLink,
+        r#"
+        SELECT id as "id!", album_id, created_at, expires_at, allow_download, max_views,
+            view_count, revoked
+        FROM share_links
+        WHERE album_id = ?
+        ORDER BY id DESC
+        "#,
+        album_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(links)
+}
+
+/// Counts a visit to a share link, returning the link if it is still usable.
+///
+/// Revoked, expired and used-up links are left untouched and return `None`.
+pub async fn use_share_link(
+    pool: &SqlitePool,
+    token_hash: &str,
+) -> Result<Option<ShareLink>, sqlx::Error> {
+    let link = sqlx::query_as!(
+        ShareLink,
+        r#"
+        UPDATE share_links
+        SET view_count = view_count + 1
+        WHERE token_hash = ?
+            AND revoked = 0
+            AND (expires_at IS NULL OR expires_at > datetime('now'))
+            AND (max_views IS NULL OR view_count < max_views)
+        RETURNING id as "id!", album_id, created_at, expires_at, allow_download, max_views,
+            view_count, revoked
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(link)
+}
+
+/// Returns a share link for the album if it hasn't been revoked or expired since
+/// it was opened.
+pub async fn get_valid_share_link(
+    pool: &SqlitePool,
+    link_id: i64,
+    album_id: i64,
+) -> Result<Option<ShareLink>, sqlx::Error> {
+    let link = sqlx::query_as!(
+        ShareLink,
+        r#"
+        SELECT id, album_id, created_at, expires_at, allow_download, max_views,
+            view_count, revoked
+        FROM share_links
+        WHERE id = ? AND album_id = ?
+            AND revoked = 0
+            AND (expires_at IS NULL OR expires_at > datetime('now'))
+        "#,
+        link_id,
         album_id
     )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(link)
+}
+
+pub async fn revoke_share_link(pool: &SqlitePool, link_id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("UPDATE share_links SET revoked = 1 WHERE id = ?", link_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn count_users(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+/// Counts the owners who can still sign in.
+pub async fn count_owners(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let count =
+        sqlx::query_scalar!("SELECT COUNT(*) FROM users WHERE role = 'owner' AND disabled = 0")
+            .fetch_one(pool)
+            .await?;
+
+    Ok(count)
+}
+
+pub async fn create_user(
+    pool: &SqlitePool,
+    username: &str,
+    password_hash: &str,
+    role: Role,
+) -> Result<i64, sqlx::Error> {
+    let role = role.as_str();
+    let result = sqlx::query!(
+        "INSERT INTO users (username, password_hash, role) VALUES (?, ?, ?)",
+        username,
+        password_hash,
+        role
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_user(pool: &SqlitePool, user_id: i64) -> Result<Option<User>, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, role as "role: Role", created_at, disabled FROM users WHERE id = ?"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Looks up a user by username (case-insensitively) along with their password hash.
+pub async fn find_user_by_username(
+    pool: &SqlitePool,
+    username: &str,
+) -> Result<Option<(User, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id as "id!", username, role as "role: Role", created_at, disabled, password_hash
+        FROM users
+        WHERE username = ?
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| {
+        (
+            User {
+                id: row.id,
+                username: row.username,
+                role: row.role,
+                created_at: row.created_at,
+                disabled: row.disabled,
+            },
+            row.password_hash,
+        )
+    }))
+}
+
+pub async fn get_users(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT id as "id!", username, role as "role: Role", created_at, disabled
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn update_user_role(
+    pool: &SqlitePool,
+    user_id: i64,
+    role: Role,
+) -> Result<(), sqlx::Error> {
+    let role = role.as_str();
+    sqlx::query!("UPDATE users SET role = ? WHERE id = ?", role, user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_user_disabled(
+    pool: &SqlitePool,
+    user_id: i64,
+    disabled: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("UPDATE users SET disabled = ? WHERE id = ?", disabled, user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn update_user_password(
+    pool: &SqlitePool,
+    user_id: i64,
+    password_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET password_hash = ? WHERE id = ?",
+        password_hash,
+        user_id
+    )
     .execute(pool)
     .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Deletes a user. Their albums are kept and left to site owners.
+pub async fn delete_user(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("UPDATE albums SET owner_id = NULL WHERE owner_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM album_permissions WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM api_tokens WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM sessions WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM oidc_identities WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    // Keep their login history, which still has the username
+    sqlx::query!("UPDATE login_events SET user_id = NULL WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!("DELETE FROM users WHERE id = ?", user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the album's owner, or `None` if the album doesn't exist. The inner value
+/// is `None` for albums without an owner.
+pub async fn get_album_owner(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Option<Option<i64>>, sqlx::Error> {
+    let owner = sqlx::query_scalar!("SELECT owner_id FROM albums WHERE id = ?", album_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(owner)
+}
+
+/// Returns the permission granted to a user on an album, if any.
+pub async fn get_album_permission(
+    pool: &SqlitePool,
+    album_id: i64,
+    user_id: i64,
+) -> Result<Option<AlbumPermission>, sqlx::Error> {
+    let permission = sqlx::query_scalar!(
+        r#"
+        SELECT permission as "permission: AlbumPermission"
+        FROM album_permissions
+        WHERE album_id = ? AND user_id = ?
+        "#,
+        album_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(permission)
+}
+
+/// Returns every album a user owns or has been granted a permission on.
+pub async fn get_user_album_permissions(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<Vec<(i64, AlbumPermission)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id as "album_id!", 'owner' as "permission!: AlbumPermission"
+        FROM albums
+        WHERE owner_id = ?
+        UNION ALL
+        SELECT album_id, permission as "permission: AlbumPermission"
+        FROM album_permissions
+        WHERE user_id = ?
+        "#,
+        user_id,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.album_id, row.permission)).collect())
+}
+
+pub async fn get_album_grants(
+    pool: &SqlitePool,
+    album_id: i64,
+) -> Result<Vec<AlbumGrant>, sqlx::Error> {
+    let grants = sqlx::query_as!(
+        AlbumGrant,
+        r#"
+        SELECT p.user_id, u.username, p.permission as "permission: AlbumPermission"
+        FROM album_permissions p
+        JOIN users u ON u.id = p.user_id
+        WHERE p.album_id = ?
+        ORDER BY u.username
+        "#,
+        album_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(grants)
+}
+
+/// Grants a user a permission on an album, replacing any they already had.
+pub async fn set_album_permission(
+    pool: &SqlitePool,
+    album_id: i64,
+    user_id: i64,
+    permission: AlbumPermission,
+) -> Result<(), sqlx::Error> {
+    let permission = permission.as_str();
+    sqlx::query!(
+        r#"
+        INSERT INTO album_permissions (album_id, user_id, permission)
+        VALUES (?, ?, ?)
+        ON CONFLICT (album_id, user_id) DO UPDATE SET permission = excluded.permission
+        "#,
+        album_id,
+        user_id,
+        permission
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn remove_album_permission(
+    pool: &SqlitePool,
+    album_id: i64,
+    user_id: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM album_permissions WHERE album_id = ? AND user_id = ?",
+        album_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the album a share link belongs to.
+pub async fn get_share_link_album(
+    pool: &SqlitePool,
+    link_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let album_id = sqlx::query_scalar!("SELECT album_id FROM share_links WHERE id = ?", link_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(album_id)
+}
+
+pub async fn get_two_factor(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<Option<TwoFactor>, sqlx::Error> {
+    let two_factor = sqlx::query_as!(
+        TwoFactor,
+        r#"
+        SELECT totp_secret as secret, totp_enabled as "enabled: bool"
+        FROM users
+        WHERE id = ?
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(two_factor)
+}
+
+/// Stores a new TOTP secret for a user who is setting up two-factor authentication.
+/// It isn't used for login until `enable_two_factor` is called.
+pub async fn set_pending_totp_secret(
+    pool: &SqlitePool,
+    user_id: i64,
+    secret: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET totp_secret = ?, totp_enabled = 0, totp_last_step = NULL WHERE id = ?",
+        secret,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Turns on two-factor authentication once the first code, used at `step`, has been confirmed.
+pub async fn enable_two_factor(
+    pool: &SqlitePool,
+    user_id: i64,
+    step: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET totp_enabled = 1, totp_last_step = ? WHERE id = ?",
+        step,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Turns off two-factor authentication and forgets the secret and recovery codes.
+pub async fn disable_two_factor(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = NULL WHERE id = ?",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records that the code for `step` was used. Returns `false` if that step or a
+/// later one was already used, so each code is only accepted once.
+pub async fn use_totp_step(pool: &SqlitePool, user_id: i64, step: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET totp_last_step = ?
+        WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)
+        "#,
+        step,
+        user_id,
+        step
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Marks an unused recovery code as used. Returns `false` if there is no such code.
+pub async fn use_recovery_code(
+    pool: &SqlitePool,
+    user_id: i64,
+    code_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE recovery_codes SET used_at = datetime('now')
+        WHERE user_id = ? AND code_hash = ? AND used_at IS NULL
+        "#,
+        user_id,
+        code_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replaces all of a user's recovery codes, used or not.
+pub async fn replace_recovery_codes(
+    pool: &SqlitePool,
+    user_id: i64,
+    code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+    for hash in code_hashes {
+        sqlx::query!(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)",
+            user_id,
+            hash
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn count_unused_recovery_codes(
+    pool: &SqlitePool,
+    user_id: i64,
+) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ? AND used_at IS NULL",
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+/// Stores a new API token by its hash.
+pub async fn create_api_token(
+    pool: &SqlitePool,
+    user_id: i64,
+    name: &str,
+    token_hash: &str,
+    scopes: &str,
+    expires_in_days: Option<i64>,
+) -> Result<i64, sqlx::Error> {
+    let expires_modifier = expires_in_days.map(|days| format!("+{} days", days));
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (user_id, name, token_hash, scopes, expires_at)
+        VALUES (?, ?, ?, ?, CASE WHEN ? IS NULL THEN NULL ELSE datetime('now', ?) END)
+        "#,
+        user_id,
+        name,
+        token_hash,
+        scopes,
+        expires_modifier,
+        expires_modifier
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn get_api_tokens(pool: &SqlitePool, user_id: i64) -> Result<Vec<ApiToken>, sqlx::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT id as "id!", name, scopes, created_at, expires_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = ?
+        ORDER BY created_at DESC, id DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// Looks up an unexpired API token by its hash and records that it was used.
+///
+/// # Returns
+/// * `Result<Option<(i64, String)>, sqlx::Error>` - The token's user id and scopes.
+///
+pub async fn use_api_token(
+    pool: &SqlitePool,
+    token_hash: &str,
+) -> Result<Option<(i64, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET last_used_at = datetime('now')
+        WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > datetime('now'))
+        RETURNING user_id, scopes
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.user_id, row.scopes)))
+}
+
+/// Deletes one of a user's API tokens. Returns `false` if they have no such token.
+pub async fn delete_api_token(
+    pool: &SqlitePool,
+    token_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM api_tokens WHERE id = ? AND user_id = ?",
+        token_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Records a new login. It stays valid for `idle_seconds` unless refreshed.
+pub async fn create_session(
+    pool: &SqlitePool,
+    jti: &str,
+    user_id: i64,
+    refresh_token_hash: &str,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+    idle_seconds: i64,
+) -> Result<i64, sqlx::Error> {
+    let expires_modifier = format!("+{} seconds", idle_seconds);
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO sessions (jti, user_id, refresh_token_hash, ip, user_agent, expires_at)
+        VALUES (?, ?, ?, ?, ?, datetime('now', ?))
+        "#,
+        jti,
+        user_id,
+        refresh_token_hash,
+        ip,
+        user_agent,
+        expires_modifier
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Looks up a session that hasn't been revoked or expired by its jti, marking it as
+/// seen now (at most once a minute, to avoid a write on every request).
+///
+/// # Returns
+/// * `Result<Option<(i64, i64)>, sqlx::Error>` - The session id and its user id.
+///
+pub async fn use_session(pool: &SqlitePool, jti: &str) -> Result<Option<(i64, i64)>, sqlx::Error> {
+    let session = sqlx::query!(
+        r#"
+        SELECT id as "id!", user_id, last_seen_at < datetime('now', '-1 minute') as "stale!: bool"
+        FROM sessions
+        WHERE jti = ? AND revoked = 0 AND expires_at > datetime('now')
+        "#,
+        jti
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(session) = session else {
+        return Ok(None);
+    };
+    if session.stale {
+        sqlx::query!(
+            "UPDATE sessions SET last_seen_at = datetime('now') WHERE id = ?",
+            session.id
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(Some((session.id, session.user_id)))
+}
+
+/// Swaps a session's refresh token for a new one and pushes back its expiry.
+/// The old refresh token is kept as the previous one, see `use_previous_refresh_token`.
+///
+/// # Returns
+/// * `Result<Option<(i64, String, i64)>, sqlx::Error>` - The session id, its jti and its
+///   user id, or `None` if the refresh token is unknown, revoked or expired.
+///
+pub async fn refresh_session(
+    pool: &SqlitePool,
+    refresh_token_hash: &str,
+    new_refresh_token_hash: &str,
+    idle_seconds: i64,
+) -> Result<Option<(i64, String, i64)>, sqlx::Error> {
+    let expires_modifier = format!("+{} seconds", idle_seconds);
+    let row = sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET previous_refresh_token_hash = refresh_token_hash, refresh_token_hash = ?,
+            refreshed_at = datetime('now'), expires_at = datetime('now', ?),
+            last_seen_at = datetime('now')
+        WHERE refresh_token_hash = ? AND revoked = 0 AND expires_at > datetime('now')
+        RETURNING id as "id!", jti, user_id
+        "#,
+        new_refresh_token_hash,
+        expires_modifier,
+        refresh_token_hash
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.id, row.jti, row.user_id)))
+}
+
+/// Looks up a session by the refresh token its last refresh replaced, if that was
+/// less than `grace_seconds` ago. Requests sent at the same time with the same
+/// refresh token all try to use it, and only the first one gets to swap it.
+///
+/// # Returns
+/// * `Result<Option<(i64, String, i64)>, sqlx::Error>` - The session id, its jti and its
+///   user id, or `None` if the refresh token wasn't replaced recently enough.
+///
+pub async fn use_previous_refresh_token(
+    pool: &SqlitePool,
+    refresh_token_hash: &str,
+    grace_seconds: i64,
+) -> Result<Option<(i64, String, i64)>, sqlx::Error> {
+    let grace_modifier = format!("-{} seconds", grace_seconds);
+    let row = sqlx::query!(
+        r#"
+        SELECT id as "id!", jti, user_id
+        FROM sessions
+        WHERE previous_refresh_token_hash = ? AND refreshed_at > datetime('now', ?)
+            AND revoked = 0 AND expires_at > datetime('now')
+        "#,
+        refresh_token_hash,
+        grace_modifier
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| (row.id, row.jti, row.user_id)))
+}
+
+/// Returns a user's sessions that haven't been revoked or expired, most recently seen first.
+pub async fn get_sessions(pool: &SqlitePool, user_id: i64) -> Result<Vec<Session>, sqlx::Error> {
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id as "id!", ip, user_agent, created_at, last_seen_at, expires_at
+        FROM sessions
+        WHERE user_id = ? AND revoked = 0 AND expires_at > datetime('now')
+        ORDER BY last_seen_at DESC, id DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Revokes one of a user's sessions. Returns `false` if they have no such session.
+pub async fn revoke_session(
+    pool: &SqlitePool,
+    session_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE sessions SET revoked = 1 WHERE id = ? AND user_id = ? AND revoked = 0",
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes every session of a user, logging them out everywhere.
+pub async fn revoke_user_sessions(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE sessions SET revoked = 1 WHERE user_id = ? AND revoked = 0",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Revokes every session of every user, e.g. after changing the JWT secret.
+pub async fn revoke_all_sessions(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("UPDATE sessions SET revoked = 1 WHERE revoked = 0")
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Forgets a user's revoked and expired sessions.
+pub async fn delete_dead_sessions(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM sessions WHERE user_id = ? AND (revoked = 1 OR expires_at <= datetime('now'))",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a login attempt.
+pub async fn record_login_event(
+    pool: &SqlitePool,
+    username: &str,
+    user_id: Option<i64>,
+    ip: Option<&str>,
+    user_agent: Option<&str>,
+    outcome: LoginOutcome,
+) -> Result<(), sqlx::Error> {
+    let outcome = outcome.as_str();
+    sqlx::query!(
+        r#"
+        INSERT INTO login_events (username, user_id, ip, user_agent, outcome)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        username,
+        user_id,
+        ip,
+        user_agent,
+        outcome
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Counts the failed logins for a username in the last day since its last
+/// successful one.
+///
+/// # Returns
+/// * `(i64, Option<i64>)` - The number of failures and how many seconds ago the
+///   last one was.
+///
+pub async fn get_username_login_failures(
+    pool: &SqlitePool,
+    username: &str,
+) -> Result<(i64, Option<i64>), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) as "failures!: i64",
+            CAST(strftime('%s', 'now') - strftime('%s', MAX(created_at)) AS INTEGER)
+                as "seconds_since_last?: i64"
+        FROM login_events
+        WHERE username = ?
+            AND outcome IN ('bad_password', 'bad_code', 'disabled')
+            AND created_at > datetime('now', '-1 day')
+            AND created_at > COALESCE(
+                (SELECT MAX(created_at) FROM login_events
+                 WHERE username = ? AND outcome = 'success'),
+                ''
+            )
+        "#,
+        username,
+        username
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((row.failures, row.seconds_since_last))
+}
+
+/// Counts the failed logins from an IP address in the last day since its last
+/// successful one. See `get_username_login_failures`.
+pub async fn get_ip_login_failures(
+    pool: &SqlitePool,
+    ip: &str,
+) -> Result<(i64, Option<i64>), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) as "failures!: i64",
+            CAST(strftime('%s', 'now') - strftime('%s', MAX(created_at)) AS INTEGER)
+                as "seconds_since_last?: i64"
+        FROM login_events
+        WHERE ip = ?
+            AND outcome IN ('bad_password', 'bad_code', 'disabled')
+            AND created_at > datetime('now', '-1 day')
+            AND created_at > COALESCE(
+                (SELECT MAX(created_at) FROM login_events
+                 WHERE ip = ? AND outcome = 'success'),
+                ''
+            )
+        "#,
+        ip,
+        ip
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((row.failures, row.seconds_since_last))
+}
+
+/// Returns the most recent login attempts, newest first, optionally only those
+/// for one username or from one IP address.
+pub async fn get_login_events(
+    pool: &SqlitePool,
+    username: Option<&str>,
+    ip: Option<&str>,
+    limit: i64,
+) -> Result<Vec<LoginEvent>, sqlx::Error> {
+    let events = sqlx::query_as!(
+        LoginEvent,
+        r#"
+        SELECT id as "id!", username, user_id, ip, user_agent,
+            outcome as "outcome: LoginOutcome", created_at
+        FROM login_events
+        WHERE (?1 IS NULL OR username = ?1) AND (?2 IS NULL OR ip = ?2)
+        ORDER BY id DESC
+        LIMIT ?3
+        "#,
+        username,
+        ip,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Forgets login attempts older than `days`.
+pub async fn delete_old_login_events(pool: &SqlitePool, days: i64) -> Result<(), sqlx::Error> {
+    let cutoff = format!("-{} days", days);
+    sqlx::query!(
+        "DELETE FROM login_events WHERE created_at < datetime('now', ?)",
+        cutoff
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns every identity provider account allowed to sign in, for the users page.
+pub async fn get_oidc_identities(pool: &SqlitePool) -> Result<Vec<OidcIdentity>, sqlx::Error> {
+    let identities = sqlx::query_as!(
+        OidcIdentity,
+        r#"
+        SELECT id as "id!", user_id, kind as "kind: OidcIdentityKind", value
+        FROM oidc_identities
+        ORDER BY user_id, kind, value
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(identities)
+}
+
+/// Lets an identity provider account sign in as a user.
+pub async fn add_oidc_identity(
+    pool: &SqlitePool,
+    user_id: i64,
+    kind: OidcIdentityKind,
+    value: &str,
+) -> Result<i64, sqlx::Error> {
+    let kind = kind.as_str();
+    let result = sqlx::query!(
+        "INSERT INTO oidc_identities (user_id, kind, value) VALUES (?, ?, ?)",
+        user_id,
+        kind,
+        value
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Removes one of a user's identity provider accounts. Returns false if it doesn't
+/// exist or belongs to another user.
+pub async fn delete_oidc_identity(
+    pool: &SqlitePool,
+    identity_id: i64,
+    user_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM oidc_identities WHERE id = ? AND user_id = ?",
+        identity_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Finds the user an identity provider account signs in as, matching its subject
+/// first and then its verified email address.
+pub async fn find_oidc_user(
+    pool: &SqlitePool,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        SELECT user_id
+        FROM oidc_identities
+        WHERE (kind = 'subject' AND value = ?) OR (kind = 'email' AND value = ?)
+        ORDER BY kind = 'subject' DESC
+        LIMIT 1
+        "#,
+        subject,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+/// Returns the versions of the migrations applied to the database so far.
+pub async fn get_applied_migrations(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    // Not checked at compile time: sqlx creates this table itself, on the first migration
+    let versions = sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success = 1")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(versions)
+}
+
+/// Returns the site's branding settings.
+pub async fn get_site_settings(pool: &SqlitePool) -> Result<SiteSettings, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT site_title, tagline, owner_name, contact_links, background_image, accent_color
+        FROM site_settings
+        WHERE id = 1
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let contact_links: Vec<ContactLink> =
+        serde_json::from_str(&row.contact_links).map_err(|e| sqlx::Error::Decode(e.into()))?;
+    Ok(SiteSettings {
+        site_title: row.site_title,
+        tagline: row.tagline,
+        owner_name: row.owner_name,
+        contact_links,
+        background_image: row.background_image,
+        accent_color: row.accent_color,
+    })
+}
+
+/// Replaces the site's branding settings.
+pub async fn update_site_settings(
+    pool: &SqlitePool,
+    settings: &SiteSettings,
+) -> Result<(), sqlx::Error> {
+    let contact_links = serde_json::to_string(&settings.contact_links)
+        .map_err(|e| sqlx::Error::Encode(e.into()))?;
+    sqlx::query!(
+        r#"
+        UPDATE site_settings
+        SET site_title = ?, tagline = ?, owner_name = ?, contact_links = ?,
+            background_image = ?, accent_color = ?, updated_at = datetime('now')
+        WHERE id = 1
+        "#,
+        settings.site_title,
+        settings.tagline,
+        settings.owner_name,
+        contact_links,
+        settings.background_image,
+        settings.accent_color
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Updates the search index entry of an album from its current name, description
+/// and equipment.
+async fn index_album(pool: &SqlitePool, album_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM album_search WHERE rowid = ?", album_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO album_search (rowid, name, description, equipment)
+        SELECT
+            id,
+            name,
+            description,
+            trim(
+                coalesce(nullif(camera_model, 'Unknown'), '') || ' ' ||
+                coalesce(nullif(lens_model, 'Unknown'), '')
+            )
+        FROM albums
+        WHERE id = ?
+        "#,
+        album_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Updates the search index entry of an image. Images without a title are found by
+/// their original filename instead, and the keywords read from the file stand in
+/// for tags until it has some.
+async fn index_image(pool: &SqlitePool, image_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM image_search WHERE rowid = ?", image_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO image_search (rowid, title, caption, keywords, equipment)
+        SELECT
+            id,
+            coalesce(title, original_filename),
+            caption,
+            coalesce(
+                (
+                    SELECT group_concat(t.name, ', ')
+                    FROM image_tags it
+                    JOIN tags t ON t.id = it.tag_id
+                    WHERE it.image_id = images.id
+                ),
+                keywords
+            ),
+            trim(
+                coalesce(nullif(camera_make, 'Unknown'), '') || ' ' ||
+                coalesce(nullif(camera_model, 'Unknown'), '') || ' ' ||
+                coalesce(nullif(lens_model, 'Unknown'), '')
+            )
+        FROM images
+        WHERE id = ?
+        "#,
+        image_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces an image's title, caption and keywords, e.g. after reading them from
+/// its file again, and tags it with the keywords.
+pub async fn update_image_description(
+    pool: &SqlitePool,
+    image_id: i64,
+    description: &ImageDescription,
+) -> Result<(), sqlx::Error> {
+    let keywords = Some(description.keywords.join(", ")).filter(|k| !k.is_empty());
+    sqlx::query!(
+        "UPDATE images SET title = ?, caption = ?, keywords = ? WHERE id = ?",
+        description.title,
+        description.caption,
+        keywords,
+        image_id
+    )
+    .execute(pool)
+    .await?;
+
+    add_keyword_tags(pool, image_id, &description.keywords).await?;
+    index_image(pool, image_id).await
+}
+
+/// Rebuilds the search indexes from scratch.
+pub async fn rebuild_search_index(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM album_search").execute(pool).await?;
+    sqlx::query!("DELETE FROM image_search").execute(pool).await?;
+
+    for album_id in get_album_ids(pool).await? {
+        index_album(pool, album_id).await?;
+    }
+    let image_ids = sqlx::query_scalar!(r#"SELECT id as "id!" FROM images"#)
+        .fetch_all(pool)
+        .await?;
+    for image_id in image_ids {
+        index_image(pool, image_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Finds public albums matching an FTS5 query, best matches first.
+pub async fn search_albums(
+    pool: &SqlitePool,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<AlbumSearchHit>, sqlx::Error> {
+    let hits = sqlx::query_as!(
+        AlbumSearchHit,
+        r#"
+        SELECT
+            a.id as "id!",
+            highlight(album_search, 0, char(1), char(2)) as "name!: String",
+            nullif(snippet(album_search, 1, char(1), char(2), '…', 24), '') as "description: String",
+            a.date,
+            coalesce(a.num_images, 0) as "num_images!: i64",
+            (
+                SELECT i.filename
+                FROM images i
+                WHERE i.album_id = a.id
+                ORDER BY i.date_created ASC
+                LIMIT 1
+            ) as "cover_image: String"
+        FROM album_search
+        JOIN albums a ON a.id = album_search.rowid
+        WHERE album_search MATCH ? AND a.visibility = 'public'
+        ORDER BY bm25(album_search, 10.0, 4.0, 2.0)
+        LIMIT ?
+        "#,
+        query,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(hits)
+}
+
+/// Finds images in public albums matching an FTS5 query, best matches first.
+pub async fn search_images(
+    pool: &SqlitePool,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<ImageSearchHit>, sqlx::Error> {
+    let hits = sqlx::query_as!(
+        ImageSearchHit,
+        r#"
+        SELECT
+            i.id as "id!",
+            i.album_id,
+            a.name as album_name,
+            i.filename,
+            nullif(highlight(image_search, 0, char(1), char(2)), '') as "title: String",
+            nullif(snippet(image_search, 1, char(1), char(2), '…', 24), '') as "caption: String",
+            nullif(highlight(image_search, 2, char(1), char(2)), '') as "keywords: String",
+            nullif(highlight(image_search, 3, char(1), char(2)), '') as "equipment: String"
+        FROM image_search
+        JOIN images i ON i.id = image_search.rowid
+        JOIN albums a ON a.id = i.album_id
+        WHERE image_search MATCH ? AND a.visibility = 'public'
+        ORDER BY bm25(image_search, 8.0, 4.0, 6.0, 2.0)
+        LIMIT ?
+        "#,
+        query,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(hits)
+}
+
+/// Finds images in public albums matching a filter, newest first.
+pub async fn filter_images(
+    pool: &SqlitePool,
+    filter: &ImageFilter,
+) -> Result<Vec<FilteredImage>, sqlx::Error> {
+    let tags_json =
+        serde_json::to_string(&filter.tags).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let images = sqlx::query_as!(
+        FilteredImage,
+        r#"
+        SELECT
+            i.id as "id!",
+            i.album_id,
+            a.name as album_name,
+            i.filename,
+            i.title,
+            i.camera_model,
+            i.lens_model,
+            i.iso,
+            i.aperture,
+            i.shutter_speed,
+            i.focal_length,
+            i.iso_value as "iso_value: i64",
+            i.aperture_value as "aperture_value: f64",
+            i.shutter_seconds as "shutter_seconds: f64",
+            i.focal_length_mm as "focal_length_mm: f64",
+            i.taken_at as "taken_at: String"
+        FROM images i
+        JOIN albums a ON a.id = i.album_id
+        WHERE a.visibility = 'public'
+            AND (?1 IS NULL OR i.camera_model = ?1)
+            AND (?2 IS NULL OR i.lens_model = ?2)
+            AND (?3 IS NULL OR i.aperture_value >= ?3)
+            AND (?4 IS NULL OR i.aperture_value <= ?4)
+            AND (?5 IS NULL OR i.iso_value >= ?5)
+            AND (?6 IS NULL OR i.iso_value <= ?6)
+            AND (?7 IS NULL OR i.shutter_seconds >= ?7)
+            AND (?8 IS NULL OR i.shutter_seconds <= ?8)
+            AND (?9 IS NULL OR i.focal_length_mm >= ?9)
+            AND (?10 IS NULL OR i.focal_length_mm <= ?10)
+            AND (?11 IS NULL OR i.taken_at >= ?11)
+            AND (?12 IS NULL OR i.taken_at < date(?12, '+1 day'))
+            AND NOT EXISTS (
+                SELECT 1
+                FROM json_each(?13) wanted
+                WHERE NOT EXISTS (
+                    SELECT 1
+                    FROM image_tags it
+                    JOIN tags t ON t.id = it.tag_id
+                    WHERE it.image_id = i.id AND t.slug = wanted.value
+                )
+            )
+            AND (?14 IS NULL OR i.rating >= ?14)
+        ORDER BY i.taken_at IS NULL, i.taken_at DESC, i.id DESC
+        "#,
+        filter.camera,
+        filter.lens,
+        filter.aperture_min,
+        filter.aperture_max,
+        filter.iso_min,
+        filter.iso_max,
+        filter.shutter_min,
+        filter.shutter_max,
+        filter.focal_length_min,
+        filter.focal_length_max,
+        filter.date_from,
+        filter.date_to,
+        tags_json,
+        filter.rating_min
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(images)
+}
+
+/// Counts the images `filter_images` would find, without loading them.
+///
+/// # Returns
+/// * `Result<(i64, Option<(i64, String)>), sqlx::Error>` - How many images match, and
+///   the album id and filename of the newest of them.
+///
+pub async fn count_filtered_images(
+    pool: &SqlitePool,
+    filter: &ImageFilter,
+) -> Result<(i64, Option<(i64, String)>), sqlx::Error> {
+    let tags_json =
+        serde_json::to_string(&filter.tags).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let row = sqlx::query!(
+        r#"
+        WITH matches AS (
+            SELECT i.id, i.album_id, i.filename, i.taken_at
+            FROM images i
+            JOIN albums a ON a.id = i.album_id
+            WHERE a.visibility = 'public'
+                AND (?1 IS NULL OR i.camera_model = ?1)
+                AND (?2 IS NULL OR i.lens_model = ?2)
+                AND (?3 IS NULL OR i.aperture_value >= ?3)
+                AND (?4 IS NULL OR i.aperture_value <= ?4)
+                AND (?5 IS NULL OR i.iso_value >= ?5)
+                AND (?6 IS NULL OR i.iso_value <= ?6)
+                AND (?7 IS NULL OR i.shutter_seconds >= ?7)
+                AND (?8 IS NULL OR i.shutter_seconds <= ?8)
+                AND (?9 IS NULL OR i.focal_length_mm >= ?9)
+                AND (?10 IS NULL OR i.focal_length_mm <= ?10)
+                AND (?11 IS NULL OR i.taken_at >= ?11)
+                AND (?12 IS NULL OR i.taken_at < date(?12, '+1 day'))
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM json_each(?13) wanted
+                    WHERE NOT EXISTS (
+                        SELECT 1
+                        FROM image_tags it
+                        JOIN tags t ON t.id = it.tag_id
+                        WHERE it.image_id = i.id AND t.slug = wanted.value
+                    )
+                )
+                AND (?14 IS NULL OR i.rating >= ?14)
+        )
+        SELECT
+            (SELECT count(*) FROM matches) as "num_images!: i64",
+            cover.album_id as "cover_album_id: i64",
+            cover.filename as "cover_image: String"
+        FROM (SELECT 1)
+        LEFT JOIN (
+            SELECT album_id, filename
+            FROM matches
+            ORDER BY taken_at IS NULL, taken_at DESC, id DESC
+            LIMIT 1
+        ) cover
+        "#,
+        filter.camera,
+        filter.lens,
+        filter.aperture_min,
+        filter.aperture_max,
+        filter.iso_min,
+        filter.iso_max,
+        filter.shutter_min,
+        filter.shutter_max,
+        filter.focal_length_min,
+        filter.focal_length_max,
+        filter.date_from,
+        filter.date_to,
+        tags_json,
+        filter.rating_min
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let cover = row.cover_album_id.zip(row.cover_image);
+    Ok((row.num_images, cover))
+}
+
+/// Images with the given ids, in the order of `image_ids`.
+pub async fn get_images(pool: &SqlitePool, image_ids: &[i64]) -> Result<Vec<Image>, sqlx::Error> {
+    let image_ids_json =
+        serde_json::to_string(image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let image_rows = sqlx::query!(
+        r#"
+        SELECT
+            i.id as "id!", i.album_id, i.filename, i.original_filename, i.checksum,
+            i.camera_make, i.camera_model, i.lens_model, i.iso, i.aperture,
+            i.shutter_speed, i.focal_length, i.light_source, i.date_created, i.file_size,
+            i.title, i.caption, i.keywords, i.rating
+        FROM json_each(?) ids
+        JOIN images i ON i.id = ids.value
+        ORDER BY ids.key
+        "#,
+        image_ids_json
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let images = image_rows
+        .into_iter()
+        .map(|row| Image {
+            id: row.id,
+            album_id: row.album_id,
+            filename: row.filename,
+            original_filename: row.original_filename,
+            checksum: row.checksum,
+            camera_make: row.camera_make,
+            camera_model: row.camera_model,
+            lens_model: row.lens_model,
+            iso: row.iso,
+            aperture: row.aperture,
+            shutter_speed: row.shutter_speed,
+            focal_length: row.focal_length,
+            light_source: row.light_source,
+            date_created: row.date_created,
+            file_size: row.file_size.unwrap_or(0),
+            title: row.title,
+            caption: row.caption,
+            keywords: row.keywords,
+            rating: row.rating,
+        })
+        .collect();
+
+    Ok(images)
+}
+
+/// Finds the tag with the same slug as `name`, creating it if there isn't one yet.
+/// Names without letters or digits can't be tags, so give `None`.
+pub async fn get_or_create_tag(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Option<Tag>, sqlx::Error> {
+    let name = name.trim();
+    let slug = tag_slug(name);
+    if slug.is_empty() {
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        "INSERT INTO tags (name, slug) VALUES (?, ?) ON CONFLICT (slug) DO NOTHING",
+        name,
+        slug
+    )
+    .execute(pool)
+    .await?;
+
+    get_tag_by_slug(pool, &slug).await
+}
+
+pub async fn get_tag_by_slug(pool: &SqlitePool, slug: &str) -> Result<Option<Tag>, sqlx::Error> {
+    let tag = sqlx::query_as!(
+        Tag,
+        r#"SELECT id as "id!", name, slug FROM tags WHERE slug = ?"#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(tag)
+}
+
+/// Tags an image with each of the keywords read from its file.
+async fn add_keyword_tags(
+    pool: &SqlitePool,
+    image_id: i64,
+    keywords: &[String],
+) -> Result<(), sqlx::Error> {
+    for keyword in keywords {
+        if let Some(tag) = get_or_create_tag(pool, keyword).await? {
+            sqlx::query!(
+                "INSERT OR IGNORE INTO image_tags (image_id, tag_id) VALUES (?, ?)",
+                image_id,
+                tag.id
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds a tag to those of the given images that are in the album.
+pub async fn add_image_tag(
+    pool: &SqlitePool,
+    album_id: i64,
+    image_ids: &[i64],
+    tag_id: i64,
+) -> Result<(), sqlx::Error> {
+    let image_ids_json =
+        serde_json::to_string(image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    sqlx::query!(
+        r#"
+        INSERT OR IGNORE INTO image_tags (image_id, tag_id)
+        SELECT id, ?
+        FROM images
+        WHERE album_id = ? AND id IN (SELECT value FROM json_each(?))
+        "#,
+        tag_id,
+        album_id,
+        image_ids_json
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes a tag from those of the given images that are in the album.
+pub async fn remove_image_tag(
+    pool: &SqlitePool,
+    album_id: i64,
+    image_ids: &[i64],
+    tag_id: i64,
+) -> Result<(), sqlx::Error> {
+    let image_ids_json =
+        serde_json::to_string(image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    sqlx::query!(
+        r#"
+        DELETE FROM image_tags
+        WHERE tag_id = ? AND image_id IN (
+            SELECT id FROM images WHERE album_id = ? AND id IN (SELECT value FROM json_each(?))
+        )
+        "#,
+        tag_id,
+        album_id,
+        image_ids_json
+    )
+    .execute(pool)
+    .await?;
+    delete_unused_tags(pool).await?;
+
+    Ok(())
+}
+
+/// Updates the search index entries of the given images of an album, after their
+/// tags changed.
+pub async fn reindex_images(
+    pool: &SqlitePool,
+    album_id: i64,
+    image_ids: &[i64],
+) -> Result<(), sqlx::Error> {
+    let image_ids_json =
+        serde_json::to_string(image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT id as "id!"
+        FROM images
+        WHERE album_id = ? AND id IN (SELECT value FROM json_each(?))
+        "#,
+        album_id,
+        image_ids_json
+    )
+    .fetch_all(pool)
+    .await?;
+    for image_id in ids {
+        index_image(pool, image_id).await?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the tags of an album itself.
+pub async fn set_album_tags(
+    pool: &SqlitePool,
+    album_id: i64,
+    tag_ids: &[i64],
+) -> Result<(), sqlx::Error> {
+    let tag_ids_json =
+        serde_json::to_string(tag_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    sqlx::query!("DELETE FROM album_tags WHERE album_id = ?", album_id)
+        .execute(pool)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT OR IGNORE INTO album_tags (album_id, tag_id)
+        SELECT ?, value FROM json_each(?)
+        "#,
+        album_id,
+        tag_ids_json
+    )
+    .execute(pool)
+    .await?;
+    delete_unused_tags(pool).await?;
+
+    Ok(())
+}
+
+pub async fn get_album_tags(pool: &SqlitePool, album_id: i64) -> Result<Vec<Tag>, sqlx::Error> {
+    let tags = sqlx::query_as!(
+        Tag,
+        r#"
+        SELECT t.id as "id!", t.name, t.slug
+        FROM album_tags at
+        JOIN tags t ON t.id = at.tag_id
+        WHERE at.album_id = ?
+        ORDER BY t.name COLLATE NOCASE
+        "#,
+        album_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tags)
+}
+
+/// Pairs each image with its tags.
+pub async fn with_image_tags(
+    pool: &SqlitePool,
+    images: Vec<Image>,
+) -> Result<Vec<TaggedImage>, sqlx::Error> {
+    let image_ids: Vec<i64> = images.iter().map(|image| image.id).collect();
+    let image_ids_json =
+        serde_json::to_string(&image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT it.image_id, t.id as "id!", t.name, t.slug
+        FROM image_tags it
+        JOIN tags t ON t.id = it.tag_id
+        WHERE it.image_id IN (SELECT value FROM json_each(?))
+        ORDER BY t.name COLLATE NOCASE
+        "#,
+        image_ids_json
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tagged: Vec<TaggedImage> = images
+        .into_iter()
+        .map(|image| TaggedImage {
+            image,
+            tags: Vec::new(),
+        })
+        .collect();
+    for row in rows {
+        if let Some(entry) = tagged.iter_mut().find(|entry| entry.image.id == row.image_id) {
+            entry.tags.push(Tag {
+                id: row.id,
+                name: row.name,
+                slug: row.slug,
+            });
+        }
+    }
+
+    Ok(tagged)
+}
+
+/// Images in public albums with a tag, newest first.
+pub async fn get_tagged_images(
+    pool: &SqlitePool,
+    tag_id: i64,
+) -> Result<Vec<Image>, sqlx::Error> {
+    let image_rows = sqlx::query!(
+        r#"
+        SELECT
+            i.id as "id!", i.album_id, i.filename, i.original_filename, i.checksum,
+            i.camera_make, i.camera_model, i.lens_model, i.iso, i.aperture,
+            i.shutter_speed, i.focal_length, i.light_source, i.date_created, i.file_size,
+            i.title, i.caption, i.keywords, i.rating
+        FROM image_tags it
+        JOIN images i ON i.id = it.image_id
+        JOIN albums a ON a.id = i.album_id
+        WHERE it.tag_id = ? AND a.visibility = 'public'
+        ORDER BY i.taken_at IS NULL, i.taken_at DESC, i.id DESC
+        "#,
+        tag_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let images = image_rows
+        .into_iter()
+        .map(|row| Image {
+            id: row.id,
+            album_id: row.album_id,
+            filename: row.filename,
+            original_filename: row.original_filename,
+            checksum: row.checksum,
+            camera_make: row.camera_make,
+            camera_model: row.camera_model,
+            lens_model: row.lens_model,
+            iso: row.iso,
+            aperture: row.aperture,
+            shutter_speed: row.shutter_speed,
+            focal_length: row.focal_length,
+            light_source: row.light_source,
+            date_created: row.date_created,
+            file_size: row.file_size.unwrap_or(0),
+            title: row.title,
+            caption: row.caption,
+            keywords: row.keywords,
+            rating: row.rating,
+        })
+        .collect();
+
+    Ok(images)
+}
+
+/// Public albums with a tag, newest first.
+pub async fn get_tagged_albums(
+    pool: &SqlitePool,
+    tag_id: i64,
+) -> Result<Vec<TaggedAlbum>, sqlx::Error> {
+    let albums = sqlx::query_as!(
+        TaggedAlbum,
+        r#"
+        SELECT
+            a.id as "id!",
+            a.name,
+            a.date,
+            coalesce(a.num_images, 0) as "num_images!: i64",
+            (
+                SELECT i.filename
+                FROM images i
+                WHERE i.album_id = a.id
+                ORDER BY i.date_created ASC
+                LIMIT 1
+            ) as "cover_image: String"
+        FROM album_tags at
+        JOIN albums a ON a.id = at.album_id
+        WHERE at.tag_id = ? AND a.visibility = 'public'
+        ORDER BY a.date DESC
+        "#,
+        tag_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(albums)
+}
+
+/// Deletes tags that no image or album has any more.
+async fn delete_unused_tags(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM tags
+        WHERE id NOT IN (SELECT tag_id FROM image_tags)
+            AND id NOT IN (SELECT tag_id FROM album_tags)
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Sets the rating of those of the given images that are in the album.
+pub async fn set_image_rating(
+    pool: &SqlitePool,
+    album_id: i64,
+    image_ids: &[i64],
+    rating: i64,
+) -> Result<(), sqlx::Error> {
+    let image_ids_json =
+        serde_json::to_string(image_ids).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    sqlx::query!(
+        r#"
+        UPDATE images
+        SET rating = ?
+        WHERE album_id = ? AND id IN (SELECT value FROM json_each(?))
+        "#,
+        rating,
+        album_id,
+        image_ids_json
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn create_smart_album(
+    pool: &SqlitePool,
+    request: &SmartAlbumRequest,
+) -> Result<i64, sqlx::Error> {
+    let filter_json =
+        serde_json::to_string(&request.filter).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO smart_albums (name, description, filter)
+        VALUES (?, ?, ?)
+        RETURNING id as "id!"
+        "#,
+        request.name,
+        request.description,
+        filter_json
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(id)
+}
+
+/// Returns whether the smart album exists.
+pub async fn update_smart_album(
+    pool: &SqlitePool,
+    smart_album_id: i64,
+    request: &SmartAlbumRequest,
+) -> Result<bool, sqlx::Error> {
+    let filter_json =
+        serde_json::to_string(&request.filter).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let result = sqlx::query!(
+        "UPDATE smart_albums SET name = ?, description = ?, filter = ? WHERE id = ?",
+        request.name,
+        request.description,
+        filter_json,
+        smart_album_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Returns whether the smart album existed.
+pub async fn delete_smart_album(
+    pool: &SqlitePool,
+    smart_album_id: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM smart_albums WHERE id = ?", smart_album_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_smart_album(
+    pool: &SqlitePool,
+    smart_album_id: i64,
+) -> Result<Option<SmartAlbum>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id as "id!", name, description, filter, created_at
+        FROM smart_albums
+        WHERE id = ?
+        "#,
+        smart_album_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| {
+        Ok(SmartAlbum {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            filter: serde_json::from_str(&row.filter)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            created_at: row.created_at,
+        })
+    })
+    .transpose()
+}
+
+/// Lists smart albums by name, each with how many images it has and the newest
+/// of them.
+pub async fn get_smart_album_summaries(
+    pool: &SqlitePool,
+) -> Result<Vec<SmartAlbumSummary>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id as "id!", name, description, filter, created_at
+        FROM smart_albums
+        ORDER BY name COLLATE NOCASE
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut summaries = Vec::new();
+    for row in rows {
+        let filter: ImageFilter =
+            serde_json::from_str(&row.filter).map_err(|e| sqlx::Error::Decode(e.into()))?;
+        let (num_images, cover) = count_filtered_images(pool, &filter).await?;
+        summaries.push(SmartAlbumSummary {
+            num_images: num_images as usize,
+            cover_album_id: cover.as_ref().map(|(album_id, _)| *album_id),
+            cover_image: cover.map(|(_, filename)| filename),
+            album: SmartAlbum {
+                id: row.id,
+                name: row.name,
+                description: row.description,
+                filter,
+                created_at: row.created_at,
+            },
+        });
+    }
+
+    Ok(summaries)
+}
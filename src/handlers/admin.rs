@@ -1,13 +1,12 @@
 use axum::{
     extract::{Multipart, Path, State},
     http::StatusCode,
-    response::{Html, IntoResponse, Redirect, Response},
+    response::{Html, IntoResponse, Response},
     Json,
 };
 use minijinja::context;
 use serde_json::{json, Value};
-use std::{fs, path::PathBuf, sync::Arc, time::Instant};
-use tower_cookies::Cookies;
+use std::{collections::HashMap, fs, path::PathBuf, sync::Arc, time::Instant};
 
 pub struct ProcessedImage {
     pub optimized: Vec<u8>,
@@ -16,30 +15,70 @@ pub struct ProcessedImage {
 }
 
 use crate::{
-    auth::middleware::require_auth,
+    auth::{
+        password::hash_password,
+        permissions::{album_permission, require_album_permission},
+        signed_urls::album_image_query,
+    },
     db::{self, create_album, update_album_metadata},
-    types::AppState,
+    types::{
+        AlbumPermission, ApiScope, AppState, CreateAlbumRequest, CurrentUser, ImageRatingRequest,
+        Role, UpdateAlbumRequest, Visibility,
+    },
     utils::{
         create_album_directory, delete_album_directory, extract_multipart_fields,
         process_and_save_images, ImageQuality,
     },
 };
 
-pub async fn admin_handler(
-    State(state): State<Arc<AppState>>,
-    cookies: Cookies,
-) -> Result<Html<String>, Redirect> {
-    require_auth(cookies, State(state.clone())).await?;
+pub async fn admin_handler(State(state): State<Arc<AppState>>, user: CurrentUser) -> Html<String> {
 
     // Get site stats
     let (album_count, image_count, total_storage) =
         db::get_site_stats(&state.pool).await.unwrap_or((0, 0, 0));
 
     // Get albums with oldest image and size
-    let albums = db::get_albums_with_oldest_image(&state.pool)
+    let albums = db::get_albums_with_oldest_image(&state.pool, true)
         .await
         .unwrap_or_default();
 
+    // Site owners manage every album; everyone else only sees albums they own or
+    // can upload to. Keyed by album id so the template can pick which actions to show.
+    let album_permissions: HashMap<String, AlbumPermission> = if user.role == Role::Owner {
+        albums
+            .iter()
+            .map(|(album, _, _)| (album.id.to_string(), AlbumPermission::Owner))
+            .collect()
+    } else {
+        db::get_user_album_permissions(&state.pool, user.id)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, permission)| *permission >= AlbumPermission::Upload)
+            .map(|(album_id, permission)| (album_id.to_string(), permission))
+            .collect()
+    };
+    let albums: Vec<_> = albums
+        .into_iter()
+        .filter(|(album, _, _)| album_permissions.contains_key(&album.id.to_string()))
+        .collect();
+    let smart_albums = db::get_smart_album_summaries(&state.pool)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load smart albums: {}", e);
+            Vec::new()
+        });
+    // Cover images of non-public albums need signed URLs, keyed by album id
+    let image_queries: HashMap<String, String> = albums
+        .iter()
+        .map(|(album, _, _)| {
+            (
+                album.id.to_string(),
+                album_image_query(&state.url_signing_key, album.id, album.visibility),
+            )
+        })
+        .collect();
+
     let reloader_guard = state.reloader.lock().await;
     let env = reloader_guard.acquire_env().unwrap();
     let tmpl = env.get_template("admin.html").unwrap();
@@ -48,22 +87,29 @@ pub async fn admin_handler(
             album_count => album_count,
             image_count => image_count,
             total_storage => (total_storage as f64 / 1024.0 / 1024.0).round(), // Convert to MB
-            albums => albums
+            albums => albums,
+            image_queries => image_queries,
+            album_permissions => album_permissions,
+            smart_albums => smart_albums,
+            current_user => user
         })
         .unwrap();
-    Ok(Html(rendered))
+    Html(rendered)
 }
 
 pub async fn create_album_handler(
     State(state): State<Arc<AppState>>,
-    cookies: Cookies,
+    user: CurrentUser,
     multipart: Multipart,
 ) -> impl IntoResponse {
     let start_total = Instant::now();
 
-    // Require authentication.
-    if let Err(redirect) = require_auth(cookies.clone(), State(state.clone())).await {
-        return redirect.into_response();
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+
+    if let Err(forbidden) = user.require(Role::Editor) {
+        return forbidden.into_response();
     }
 
     // ===== Multipart Extraction =====
@@ -80,15 +126,41 @@ pub async fn create_album_handler(
         None => return (StatusCode::BAD_REQUEST, "Missing album data").into_response(),
     };
 
+    let password_hash = match album_password_hash(&album_data) {
+        Ok(Some(hash)) => Some(hash),
+        Ok(None) if album_data.visibility == Visibility::Password => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Password-protected albums need a password",
+            )
+                .into_response()
+        }
+        Ok(None) => None,
+        Err(_) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash album password")
+                .into_response()
+        }
+    };
+
     // ===== Album Creation & Directory Setup =====
     let start_album_creation = Instant::now();
-    let album_id = match create_album(&state.pool, &album_data).await {
+    let album_id = match create_album(&state.pool, &album_data, Some(user.id)).await {
         Ok(id) => id,
         Err(_) => {
             return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create album").into_response()
         }
     };
 
+    if let Some(hash) = &password_hash {
+        if db::set_album_password_hash(&state.pool, album_id, Some(hash))
+            .await
+            .is_err()
+        {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set album password")
+                .into_response();
+        }
+    }
+
     if let Err(_) = create_album_directory(album_id).await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -100,8 +172,9 @@ pub async fn create_album_handler(
 
     // ===== Image Processing (Concurrent) =====
     let start_image_processing = Instant::now();
-    let processed_images = match process_and_save_images(state.clone(), album_id, image_data).await
-    {
+    let processed =
+        process_and_save_images(&state.pool, &state.config.images, album_id, image_data).await;
+    let processed_images = match processed {
         Ok(count) => count,
         Err(e) => {
             return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
@@ -133,44 +206,118 @@ pub async fn create_album_handler(
 pub async fn update_album_handler(
     Path(album_id): Path<i64>,
     State(state): State<Arc<AppState>>,
-    cookies: Cookies,
+    user: CurrentUser,
     multipart: Multipart,
 ) -> impl IntoResponse {
     let start_total = Instant::now();
 
-    // Authentication check
-    if let Err(redirect) = require_auth(cookies, State(state.clone())).await {
-        return redirect.into_response();
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
     }
 
+    // Checked before reading the upload; changing anything else needs edit permission
+    let permission = match album_permission(&state.pool, &user, album_id).await {
+        Ok(Some(permission)) if permission >= AlbumPermission::Upload => permission,
+        Ok(Some(_)) => {
+            return (StatusCode::FORBIDDEN, "You don't have permission to do that").into_response()
+        }
+        Ok(None) => return (StatusCode::NOT_FOUND, "Album not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
     // ===== Multipart Extraction =====
     let (album_data, new_images, deleted_image_ids) =
-        match extract_multipart_fields(multipart, "album", "new_images", Some("deleted_images"))
+        match extract_multipart_fields::<UpdateAlbumRequest>(
+            multipart,
+            "album",
+            "new_images",
+            Some("deleted_images"),
+        )
             .await
         {
             Ok(result) => result,
             Err(resp) => return resp.into_response(),
         };
 
+    if (album_data.is_some() || !deleted_image_ids.is_empty())
+        && permission < AlbumPermission::Edit
+    {
+        return (
+            StatusCode::FORBIDDEN,
+            "You can only add images to this album",
+        )
+            .into_response();
+    }
+
     // Update album metadata if provided
     if let Some(album_data) = &album_data {
+        let current_visibility = match db::get_album_visibility(&state.pool, album_id).await {
+            Ok(Some(visibility)) => visibility,
+            Ok(None) => return (StatusCode::NOT_FOUND, "Album not found").into_response(),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let visibility = album_data.visibility.unwrap_or(current_visibility);
+        let password = album_data.password.as_deref().filter(|p| !p.is_empty());
+
+        // The password hash to store, if it changes. A blank password keeps the existing
+        // one, which must then exist; albums that stop being password-protected lose it.
+        let password_hash = match (visibility, password) {
+            (Visibility::Password, Some(password)) => match hash_password(password) {
+                Ok(hash) => Some(Some(hash)),
+                Err(_) => {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash album password")
+                        .into_response()
+                }
+            },
+            (Visibility::Password, None) => {
+                match db::get_album_password_hash(&state.pool, album_id).await {
+                    Ok(Some(_)) => None,
+                    Ok(None) => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            "Password-protected albums need a password",
+                        )
+                            .into_response()
+                    }
+                    Err(e) => {
+                        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                    }
+                }
+            }
+            _ if current_visibility == Visibility::Password => Some(None),
+            _ => None,
+        };
+
         if let Err(e) = db::update_album_details(
             &state.pool,
             album_id,
             &album_data.name,
             &album_data.description,
             &album_data.date,
+            album_data.allow_download,
+            album_data.visibility,
         )
         .await
         {
             return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
         }
+
+        if let Some(password_hash) = password_hash {
+            if let Err(e) =
+                db::set_album_password_hash(&state.pool, album_id, password_hash.as_deref()).await
+            {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        }
     }
 
     // Delete requested images
     let mut deleted_count = 0;
     for image_id in deleted_image_ids {
         match db::get_image(&state.pool, image_id).await {
+            Ok(Some(image)) if image.album_id != album_id => {
+                println!("Image ID {} is not in album {}", image_id, album_id);
+            }
             Ok(Some(image)) => {
                 // Delete from database
                 if let Err(e) = db::delete_image(&state.pool, image_id).await {
@@ -202,8 +349,9 @@ pub async fn update_album_handler(
     }
 
     // Process new images
-    let processed_images = match process_and_save_images(state.clone(), album_id, new_images).await
-    {
+    let processed =
+        process_and_save_images(&state.pool, &state.config.images, album_id, new_images).await;
+    let processed_images = match processed {
         Ok(count) => count,
         Err(e) => {
             return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
@@ -226,15 +374,29 @@ pub async fn update_album_handler(
     .into_response()
 }
 
+/// Hashes the password submitted with a password-protected album.
+///
+/// Returns `None` for other visibilities and when no password was entered.
+fn album_password_hash(
+    album: &CreateAlbumRequest,
+) -> Result<Option<String>, argon2::password_hash::Error> {
+    match album.password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) if album.visibility == Visibility::Password => {
+            hash_password(password).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
 pub async fn delete_album_handler(
     Path(album_id): Path<i64>,
     State(state): State<Arc<AppState>>,
-    cookies: Cookies,
+    user: CurrentUser,
 ) -> Result<Json<Value>, (StatusCode, String)> {
-    // Require authentication
-    if let Err(_) = require_auth(cookies, State(state.clone())).await {
-        return Err((StatusCode::UNAUTHORIZED, "Unauthorized".to_string()));
-    }
+    user.require_scope(ApiScope::AlbumsWrite)
+        .map_err(|(status, message)| (status, message.to_string()))?;
+
+    require_album_permission(&state.pool, &user, album_id, AlbumPermission::Owner).await?;
 
     // Delete from database
     if let Err(e) = db::delete_album(&state.pool, album_id).await {
@@ -255,10 +417,10 @@ pub async fn delete_album_handler(
 pub async fn delete_image_handler(
     Path(image_id): Path<i64>,
     State(state): State<Arc<AppState>>,
-    cookies: Cookies,
+    user: CurrentUser,
 ) -> Response {
-    if let Err(_) = require_auth(cookies, State(state.clone())).await {
-        return StatusCode::UNAUTHORIZED.into_response();
+    if let Err(forbidden) = user.require_scope(ApiScope::ImagesDelete) {
+        return forbidden.into_response();
     }
 
     // Retrieve image information from the database
@@ -268,6 +430,12 @@ pub async fn delete_image_handler(
         Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, image.album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+
     // Delete from database
     if let Err(e) = db::delete_image(&state.pool, image_id).await {
         return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
@@ -289,30 +457,86 @@ pub async fn delete_image_handler(
     Json(json!({"status": "success"})).into_response()
 }
 
-pub async fn get_album_handler(
+/// Sets the star rating of the selected images of an album. Image ids from other
+/// albums are ignored.
+pub async fn rate_images_handler(
     Path(album_id): Path<i64>,
     State(state): State<Arc<AppState>>,
-    cookies: Cookies,
+    user: CurrentUser,
+    Json(request): Json<ImageRatingRequest>,
 ) -> Response {
-    // Require authentication
-    if let Err(redirect) = require_auth(cookies, State(state.clone())).await {
-        return redirect.into_response();
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+    if request.image_ids.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No images selected").into_response();
+    }
+    if !(0..=5).contains(&request.rating) {
+        return (StatusCode::BAD_REQUEST, "Ratings are from 0 to 5 stars").into_response();
     }
 
-    match db::get_album_with_images(&state.pool, album_id).await {
-        Ok((album, images)) => Json(json!({
-            "status": "success",
-            "album": album,
-            "images": images.iter().map(|img| {
-                json!({
-                    "id": img.id,
-                    "name": img.filename,
-                    "thumbnail": format!("/uploads/{}/thumbnail/{}", album_id, img.filename),
-                    "size": (img.file_size as f64 / 1024.0 / 1024.0).round()
-                })
-            }).collect::<Vec<_>>()
-        }))
-        .into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    let result =
+        db::set_image_rating(&state.pool, album_id, &request.image_ids, request.rating).await;
+    if let Err(e) = result {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
     }
+
+    Json(json!({"status": "success"})).into_response()
+}
+
+pub async fn get_album_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsRead) {
+        return forbidden.into_response();
+    }
+
+    let permission = match album_permission(&state.pool, &user, album_id).await {
+        Ok(Some(permission)) if permission >= AlbumPermission::Upload => permission,
+        Ok(Some(_)) => {
+            return (StatusCode::FORBIDDEN, "You don't have permission to do that").into_response()
+        }
+        Ok(None) => return (StatusCode::NOT_FOUND, "Album not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let (album, images) = match db::get_album_with_images(&state.pool, album_id).await {
+        Ok(result) => result,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let tags = db::get_album_tags(&state.pool, album_id).await;
+    let images = db::with_image_tags(&state.pool, images).await;
+    let (tags, images) = match (tags, images) {
+        (Ok(tags), Ok(images)) => (tags, images),
+        (Err(e), _) | (_, Err(e)) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    };
+
+    let image_query = album_image_query(&state.url_signing_key, album.id, album.visibility);
+    Json(json!({
+        "status": "success",
+        "album": album,
+        "permission": permission,
+        "tags": tags,
+        "images": images.iter().map(|tagged| {
+            let img = &tagged.image;
+            json!({
+                "id": img.id,
+                "name": img.filename,
+                "thumbnail": format!("/uploads/{}/thumbnail/{}{}", album_id, img.filename, image_query),
+                "size": (img.file_size as f64 / 1024.0 / 1024.0).round(),
+                "tags": tagged.tags,
+                "rating": img.rating
+            })
+        }).collect::<Vec<_>>()
+    }))
+    .into_response()
 }
@@ -1,19 +1,72 @@
-use crate::{db::get_album_with_images, types::AppState};
+use crate::{
+    archive::{archive_entry_names, image_path, stream_zip},
+    auth::album_access::{album_access, grant_album_access},
+    auth::password::verify_password,
+    auth::signed_urls::album_image_query,
+    db::{get_album_password_hash, get_album_tags, get_album_with_images, with_image_tags},
+    types::{Album, AppState, Image, Visibility},
+    utils::ImageQuality,
+};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
 };
 use minijinja::context;
+use serde::Deserialize;
 use sqlx::SqlitePool;
+use std::collections::HashSet;
 use std::sync::Arc;
+use tower_cookies::Cookies;
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    #[serde(default = "default_download_quality")]
+    quality: ImageQuality,
+}
+
+#[derive(Deserialize)]
+pub struct SelectedDownloadForm {
+    /// Comma-separated image ids
+    ids: String,
+    #[serde(default = "default_download_quality")]
+    quality: ImageQuality,
+}
+
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    password: String,
+}
+
+fn default_download_quality() -> ImageQuality {
+    ImageQuality::Full
+}
 
 pub async fn album_handler(
     Path(album_id): Path<i64>,
     State(state): State<Arc<AppState>>,
+    cookies: Cookies,
 ) -> Response {
     match get_album_with_images(&state.pool, album_id).await {
         Ok((album, images)) => {
+            let Some(access) = album_access(&cookies, &state, album.id, album.visibility).await
+            else {
+                return match album.visibility {
+                    Visibility::Password => {
+                        album_password_response(&state, &album, None).await
+                    }
+                    _ => StatusCode::NOT_FOUND.into_response(),
+                };
+            };
+
+            let tags = get_album_tags(&state.pool, album_id).await;
+            let images = with_image_tags(&state.pool, images).await;
+            let (tags, images) = match (tags, images) {
+                (Ok(tags), Ok(images)) => (tags, images),
+                _ => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            };
+
             // Fetch previous and next valid album IDs
             let (prev_album, next_album) = get_adjacent_albums(&state.pool, album_id).await;
 
@@ -23,7 +76,10 @@ pub async fn album_handler(
 
             Html(
                 tmpl.render(context! {
+                    can_download => album.allow_download || access.share_allows_download,
+                    image_query => album_image_query(&state.url_signing_key, album.id, album.visibility),
                     album => album,
+                    tags => tags,
                     images => images,
                     prev_album => prev_album,
                     next_album => next_album,
@@ -36,6 +92,52 @@ pub async fn album_handler(
     }
 }
 
+/// Checks the password of a password-protected album and, if it matches, issues
+/// the cookie that unlocks it.
+pub async fn album_unlock_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    Form(form): Form<UnlockForm>,
+) -> Response {
+    let (album, _) = match get_album_with_images(&state.pool, album_id).await {
+        Ok(result) => result,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    if album.visibility != Visibility::Password {
+        return Redirect::to(&format!("/albums/{}", album_id)).into_response();
+    }
+
+    let password_hash = get_album_password_hash(&state.pool, album_id)
+        .await
+        .ok()
+        .flatten();
+    if !password_hash.is_some_and(|hash| verify_password(&form.password, &hash)) {
+        return album_password_response(&state, &album, Some("Incorrect password")).await;
+    }
+
+    if grant_album_access(&cookies, &state.jwt_secret, album_id, None).is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
+    }
+    Redirect::to(&format!("/albums/{}", album_id)).into_response()
+}
+
+/// Renders the password prompt for a locked album.
+async fn album_password_response(state: &AppState, album: &Album, error: Option<&str>) -> Response {
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("album_password.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            album_id => album.id,
+            album_name => album.name,
+            error => error,
+        })
+        .unwrap();
+    (StatusCode::UNAUTHORIZED, Html(rendered)).into_response()
+}
+
+/// Returns the public albums dated immediately before and after the given album.
 pub async fn get_adjacent_albums(pool: &SqlitePool, album_id: i64) -> (Option<i64>, Option<i64>) {
     // First get the current album's date
     let current_album_date = sqlx::query_scalar!(
@@ -54,7 +156,7 @@ pub async fn get_adjacent_albums(pool: &SqlitePool, album_id: i64) -> (Option<i6
     
     // Get the previous album (closest older by date)
     let prev_album = sqlx::query_scalar!(
-        "SELECT id FROM albums WHERE date < ? ORDER BY date DESC LIMIT 1",
+        "SELECT id FROM albums WHERE date < ? AND visibility = 'public' ORDER BY date DESC LIMIT 1",
         current_date
     )
     .fetch_optional(pool)
@@ -64,7 +166,7 @@ pub async fn get_adjacent_albums(pool: &SqlitePool, album_id: i64) -> (Option<i6
 
     // Get the next album (closest newer by date)
     let next_album = sqlx::query_scalar!(
-        "SELECT id FROM albums WHERE date > ? ORDER BY date ASC LIMIT 1",
+        "SELECT id FROM albums WHERE date > ? AND visibility = 'public' ORDER BY date ASC LIMIT 1",
         current_date
     )
     .fetch_optional(pool)
@@ -74,3 +176,141 @@ pub async fn get_adjacent_albums(pool: &SqlitePool, album_id: i64) -> (Option<i6
 
     (prev_album, next_album)
 }
+
+/// Streams a ZIP of every image in an album, if the album or the share link it was
+/// opened with allows downloads.
+pub async fn album_download_handler(
+    Path(album_id): Path<i64>,
+    Query(query): Query<DownloadQuery>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+) -> Response {
+    let (album, images) = match get_album_with_images(&state.pool, album_id).await {
+        Ok(result) => result,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let Some(access) = album_access(&cookies, &state, album.id, album.visibility).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if !album.allow_download && !access.share_allows_download {
+        return (StatusCode::FORBIDDEN, "Downloads are disabled for this album").into_response();
+    }
+
+    zip_download_response(&album, &images, query.quality)
+}
+
+/// Streams a ZIP of the selected images in an album, if the album or the share link
+/// it was opened with allows downloads.
+pub async fn selected_download_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    Form(form): Form<SelectedDownloadForm>,
+) -> Response {
+    let requested: HashSet<i64> = match form
+        .ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::parse)
+        .collect()
+    {
+        Ok(ids) => ids,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid image ids").into_response(),
+    };
+    if requested.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No images selected").into_response();
+    }
+
+    let (album, images) = match get_album_with_images(&state.pool, album_id).await {
+        Ok(result) => result,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let Some(access) = album_access(&cookies, &state, album.id, album.visibility).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if !album.allow_download && !access.share_allows_download {
+        return (StatusCode::FORBIDDEN, "Downloads are disabled for this album").into_response();
+    }
+
+    let selected: Vec<Image> = images
+        .into_iter()
+        .filter(|image| requested.contains(&image.id))
+        .collect();
+    if selected.len() != requested.len() {
+        return (StatusCode::BAD_REQUEST, "Some images are not part of this album").into_response();
+    }
+
+    zip_download_response(&album, &selected, form.quality)
+}
+
+/// Builds a streaming ZIP response of `images` at the given quality, named after the album.
+pub fn zip_download_response(album: &Album, images: &[Image], quality: ImageQuality) -> Response {
+    if matches!(quality, ImageQuality::Thumbnail) {
+        return (StatusCode::BAD_REQUEST, "Only full or optimized images can be downloaded")
+            .into_response();
+    }
+
+    let entries = archive_entry_names(images)
+        .into_iter()
+        .zip(images)
+        .map(|(name, image)| (name, image_path(album.id, quality, &image.filename)))
+        .collect();
+
+    let filename = match quality {
+        ImageQuality::Full => format!("{}.zip", sanitize_filename(&album.name)),
+        _ => format!("{} ({}).zip", sanitize_filename(&album.name), quality.as_str()),
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition(&filename)),
+        ],
+        stream_zip(entries),
+    )
+        .into_response()
+}
+
+/// Strips characters that aren't safe in a download filename.
+fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.' | '(' | ')') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim_matches(|c: char| c == '.' || c.is_whitespace());
+
+    if cleaned.is_empty() {
+        "album".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Builds an attachment header with an ASCII fallback and the UTF-8 name (RFC 6266).
+fn content_disposition(filename: &str) -> String {
+    let ascii: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-_.".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii, encoded)
+}
@@ -0,0 +1,95 @@
+use crate::{
+    auth::api_tokens::generate_api_token,
+    db,
+    types::{AppState, CreateApiTokenRequest, CurrentUser},
+    utils::hash_token,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Lists the logged-in user's API tokens and lets them create and revoke them.
+pub async fn api_tokens_page_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    let tokens = db::get_api_tokens(&state.pool, user.id)
+        .await
+        .unwrap_or_default();
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("api_tokens.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            tokens => tokens,
+            current_user => user,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+/// Creates an API token for the logged-in user. The token is only returned here;
+/// only its hash is stored.
+pub async fn create_api_token_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<CreateApiTokenRequest>,
+) -> Response {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Name is required").into_response();
+    }
+    if request.scopes.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Choose at least one scope").into_response();
+    }
+    if request.expires_in_days.is_some_and(|days| days <= 0) {
+        return (StatusCode::BAD_REQUEST, "Expiry must be positive").into_response();
+    }
+
+    let mut scopes: Vec<&str> = request.scopes.iter().map(|scope| scope.as_str()).collect();
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    let token = generate_api_token();
+    match db::create_api_token(
+        &state.pool,
+        user.id,
+        name,
+        &hash_token(&token),
+        &scopes.join(" "),
+        request.expires_in_days,
+    )
+    .await
+    {
+        Ok(id) => Json(json!({
+            "status": "success",
+            "id": id,
+            "token": token,
+        }))
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn delete_api_token_handler(
+    Path(token_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    match db::delete_api_token(&state.pool, token_id, user.id).await {
+        Ok(true) => Json(json!({
+            "status": "success",
+            "message": "API token revoked",
+        }))
+        .into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "API token not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    db,
+    types::{AppState, FilteredImage, ImageFilter},
+    utils::tag_slug,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Images returned per request unless `limit` asks for fewer or more.
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 500;
+
+/// Filters as given in the query string. Numbers may be written the way they are
+/// shown, e.g. `f/1.8`, `ISO 3200`, `1/200` or `85mm`.
+#[derive(Deserialize)]
+pub struct ImageSearchQuery {
+    camera: Option<String>,
+    lens: Option<String>,
+    aperture_min: Option<String>,
+    aperture_max: Option<String>,
+    iso_min: Option<String>,
+    iso_max: Option<String>,
+    shutter_min: Option<String>,
+    shutter_max: Option<String>,
+    focal_length_min: Option<String>,
+    focal_length_max: Option<String>,
+    date_from: Option<String>,
+    date_to: Option<String>,
+    /// Comma-separated tag names
+    tags: Option<String>,
+    rating_min: Option<String>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct ImageSearchResults {
+    /// How many images match, of which `images` is one page
+    total: usize,
+    images: Vec<FilteredImage>,
+    facets: ExifFacets,
+}
+
+/// How many of the matching images have each value.
+#[derive(Serialize)]
+pub struct ExifFacets {
+    camera: Vec<FacetCount>,
+    lens: Vec<FacetCount>,
+    aperture: Vec<FacetCount>,
+    iso: Vec<FacetCount>,
+    shutter: Vec<FacetCount>,
+    focal_length: Vec<FacetCount>,
+    year: Vec<FacetCount>,
+}
+
+#[derive(Serialize)]
+pub struct FacetCount {
+    value: String,
+    count: usize,
+}
+
+pub async fn browse_page_handler(State(state): State<Arc<AppState>>) -> Html<String> {
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("browse.html").unwrap();
+    let rendered = tmpl.render(context! {}).unwrap();
+    Html(rendered)
+}
+
+/// Lists images in public albums matching the filters, newest first, with counts
+/// of each camera, lens, aperture, ISO, shutter speed, focal length and year
+/// among all the matches.
+pub async fn image_search_handler(
+    Query(query): Query<ImageSearchQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let filter = match parse_filter(&query) {
+        Ok(filter) => filter,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let matches = match db::filter_images(&state.pool, &filter).await {
+        Ok(images) => images,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let facets = count_facets(&matches);
+    let total = matches.len();
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let images = matches.into_iter().skip(query.offset).take(limit).collect();
+
+    Json(ImageSearchResults {
+        total,
+        images,
+        facets,
+    })
+    .into_response()
+}
+
+fn parse_filter(query: &ImageSearchQuery) -> Result<ImageFilter, String> {
+    Ok(ImageFilter {
+        camera: non_empty(&query.camera),
+        lens: non_empty(&query.lens),
+        aperture_min: parse_param("aperture_min", &query.aperture_min, parse_aperture)?,
+        aperture_max: parse_param("aperture_max", &query.aperture_max, parse_aperture)?,
+        iso_min: parse_param("iso_min", &query.iso_min, parse_iso)?,
+        iso_max: parse_param("iso_max", &query.iso_max, parse_iso)?,
+        shutter_min: parse_param("shutter_min", &query.shutter_min, parse_shutter)?,
+        shutter_max: parse_param("shutter_max", &query.shutter_max, parse_shutter)?,
+        focal_length_min: parse_param(
+            "focal_length_min",
+            &query.focal_length_min,
+            parse_focal_length,
+        )?,
+        focal_length_max: parse_param(
+            "focal_length_max",
+            &query.focal_length_max,
+            parse_focal_length,
+        )?,
+        date_from: parse_param("date_from", &query.date_from, parse_date)?,
+        date_to: parse_param("date_to", &query.date_to, parse_date)?,
+        tags: non_empty(&query.tags)
+            .map(|tags| parse_tags(&tags))
+            .unwrap_or_default(),
+        rating_min: parse_param("rating_min", &query.rating_min, parse_rating)?,
+    })
+}
+
+/// Parses an optional query parameter, treating an empty one as unset.
+fn parse_param<T>(
+    name: &str,
+    value: &Option<String>,
+    parse: fn(&str) -> Option<T>,
+) -> Result<Option<T>, String> {
+    match non_empty(value) {
+        Some(value) => parse(&value)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid {}: '{}'", name, value)),
+        None => Ok(None),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+/// `1.8` or `f/1.8`
+fn parse_aperture(value: &str) -> Option<f64> {
+    let value = value.strip_prefix("f/").unwrap_or(value);
+    value.parse().ok().filter(|f: &f64| *f > 0.0)
+}
+
+/// `3200` or `ISO 3200`
+fn parse_iso(value: &str) -> Option<i64> {
+    let value = value.strip_prefix("ISO").unwrap_or(value);
+    value.trim().parse().ok()
+}
+
+/// `1/200`, `0.5` or either with ` s` after it, in seconds
+fn parse_shutter(value: &str) -> Option<f64> {
+    let value = value.strip_suffix('s').unwrap_or(value).trim();
+    let seconds = match value.strip_prefix("1/") {
+        Some(denominator) => 1.0 / denominator.parse::<f64>().ok()?,
+        None => value.parse().ok()?,
+    };
+    Some(seconds).filter(|s: &f64| s.is_finite() && *s > 0.0)
+}
+
+/// `85` or `85 mm`
+fn parse_focal_length(value: &str) -> Option<f64> {
+    let value = value.strip_suffix("mm").unwrap_or(value);
+    value.trim().parse().ok().filter(|f: &f64| *f > 0.0)
+}
+
+/// `YYYY-MM-DD`
+pub fn parse_date(value: &str) -> Option<String> {
+    let is_date = value.len() == 10
+        && value
+            .char_indices()
+            .all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_ascii_digit() });
+    is_date.then(|| value.to_string())
+}
+
+/// `1` to `5` stars
+fn parse_rating(value: &str) -> Option<i64> {
+    value.parse().ok().filter(|rating| (1..=5).contains(rating))
+}
+
+/// The slugs of comma-separated tag names, without repeats.
+pub fn parse_tags(names: &str) -> Vec<String> {
+    let mut slugs: Vec<String> = Vec::new();
+    for slug in names.split(',').map(tag_slug) {
+        if !slug.is_empty() && !slugs.contains(&slug) {
+            slugs.push(slug);
+        }
+    }
+    slugs
+}
+
+fn count_facets(images: &[FilteredImage]) -> ExifFacets {
+    let mut camera = FacetCounter::default();
+    let mut lens = FacetCounter::default();
+    let mut aperture = FacetCounter::default();
+    let mut iso = FacetCounter::default();
+    let mut shutter = FacetCounter::default();
+    let mut focal_length = FacetCounter::default();
+    let mut year = FacetCounter::default();
+
+    for image in images {
+        camera.add(image.camera_model.as_deref(), 0.0);
+        lens.add(image.lens_model.as_deref(), 0.0);
+        if let Some(value) = image.aperture_value {
+            aperture.add(image.aperture.as_deref(), value);
+        }
+        if let Some(value) = image.iso_value {
+            iso.add(image.iso.as_deref(), value as f64);
+        }
+        if let Some(value) = image.shutter_seconds {
+            // Longest first, like the apertures go from widest to narrowest
+            shutter.add(image.shutter_speed.as_deref(), -value);
+        }
+        if let Some(value) = image.focal_length_mm {
+            focal_length.add(image.focal_length.as_deref(), value);
+        }
+        if let Some(taken_at) = &image.taken_at {
+            let taken_year = &taken_at[..4];
+            year.add(Some(taken_year), -taken_year.parse::<f64>().unwrap_or(0.0));
+        }
+    }
+
+    ExifFacets {
+        // Most used first
+        camera: camera.by_count(),
+        lens: lens.by_count(),
+        aperture: aperture.by_value(),
+        iso: iso.by_value(),
+        shutter: shutter.by_value(),
+        focal_length: focal_length.by_value(),
+        // Newest first
+        year: year.by_value(),
+    }
+}
+
+/// Counts the images with each value, remembering a number to order values by.
+#[derive(Default)]
+struct FacetCounter {
+    counts: HashMap<String, (f64, usize)>,
+}
+
+impl FacetCounter {
+    fn add(&mut self, value: Option<&str>, order: f64) {
+        let Some(value) = value.filter(|value| *value != "Unknown" && !value.is_empty()) else {
+            return;
+        };
+        self.counts.entry(value.to_string()).or_insert((order, 0)).1 += 1;
+    }
+
+    fn by_count(self) -> Vec<FacetCount> {
+        let mut counts: Vec<_> = self.counts.into_iter().collect();
+        counts.sort_by(|(a, (_, a_count)), (b, (_, b_count))| {
+            b_count.cmp(a_count).then_with(|| a.cmp(b))
+        });
+        into_facet_counts(counts)
+    }
+
+    fn by_value(self) -> Vec<FacetCount> {
+        let mut counts: Vec<_> = self.counts.into_iter().collect();
+        counts.sort_by(|(a, (a_order, _)), (b, (b_order, _))| {
+            a_order.total_cmp(b_order).then_with(|| a.cmp(b))
+        });
+        into_facet_counts(counts)
+    }
+}
+
+fn into_facet_counts(counts: Vec<(String, (f64, usize))>) -> Vec<FacetCount> {
+    counts
+        .into_iter()
+        .map(|(value, (_, count))| FacetCount { value, count })
+        .collect()
+}
@@ -1,18 +1,28 @@
-use crate::{db::get_albums_with_oldest_image, types::AppState};
+use crate::{
+    db::{get_albums_with_oldest_image, get_smart_album_summaries},
+    types::AppState,
+};
 use axum::{extract::State, response::Html};
 use minijinja::context;
 use std::sync::Arc;
 
 pub async fn home_handler(State(state): State<Arc<AppState>>) -> Html<String> {
-    let albums = get_albums_with_oldest_image(&state.pool)
+    let albums = get_albums_with_oldest_image(&state.pool, false)
         .await
         .unwrap_or_default();
+    let smart_albums = get_smart_album_summaries(&state.pool)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load smart albums: {}", e);
+            Vec::new()
+        });
 
     let reloader_guard = state.reloader.lock().await;
     let env = reloader_guard.acquire_env().unwrap();
     let tmpl = env.get_template("home.html").unwrap();
     let rendered = tmpl.render(context! {
         albums => albums,
+        smart_albums => smart_albums,
     })
     .unwrap();
     Html(rendered)
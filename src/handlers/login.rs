@@ -1,131 +1,326 @@
-use crate::auth::middleware::is_authenticated;
-use crate::types::{AppState, Claims};
-use argon2::{PasswordHash, PasswordVerifier};
-use axum::http::{header, StatusCode};
+use crate::auth::login_throttle::{lockout_message, lockout_remaining, record_login};
+use crate::auth::middleware::{current_user, is_authenticated};
+use crate::auth::password::verify_password;
+use crate::auth::sessions::{clear_session_cookies, start_session};
+use crate::auth::two_factor::{hash_recovery_code, verify_code};
+use crate::db::{self, find_user_by_username};
+use crate::types::{AppState, LoginOutcome, TwoFactorChallengeClaims};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, State},
     response::{Html, Redirect, Response},
     Form,
 };
-use argon2::Argon2;
-use jsonwebtoken::{encode, EncodingKey, Header};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use minijinja::context;
 use serde::Deserialize;
+use std::net::SocketAddr;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::{env, sync::Arc};
+use std::sync::Arc;
 use tower_cookies::cookie::time::{Duration, OffsetDateTime};
 use tower_cookies::cookie::CookieBuilder;
 use tower_cookies::{Cookie, Cookies};
 
+/// Audience of the two-factor challenge cookie, see `TwoFactorChallengeClaims`.
+const TWO_FACTOR_AUDIENCE: &str = "two-factor";
+/// How long the user has to enter their code after the password, in seconds.
+const TWO_FACTOR_CHALLENGE_DURATION: i64 = 300;
+
 #[derive(Deserialize)]
 pub struct LoginForm {
     username: String,
     password: String,
 }
 
+#[derive(Deserialize)]
+pub struct TwoFactorForm {
+    code: String,
+}
+
 pub async fn login_handler(
     State(state): State<Arc<AppState>>,
     cookies: Cookies,
 ) -> Result<Html<String>, Redirect> {
     // If already authenticated, redirect to admin page
-    if is_authenticated(&cookies, &state.jwt_secret).await {
+    if is_authenticated(&cookies, &state).await {
         return Err(Redirect::to("/admin"));
     }
 
-    let reloader_guard = state.reloader.lock().await;
-    let env = reloader_guard.acquire_env().unwrap();
-    let tmpl = env.get_template("login.html").unwrap();
-    let rendered = tmpl
-        .render(context! {
-            error => Option::<String>::None
-        })
-        .unwrap();
-    Ok(Html(rendered))
+    Ok(login_page(&state, "", false).await)
 }
 
 pub async fn login_post_handler(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     cookies: Cookies,
     Form(form): Form<LoginForm>,
 ) -> Result<Response<axum::body::Body>, Html<String>> {
-    let admin_username = env::var("ADMIN_USERNAME").expect("ADMIN_USERNAME must be set");
-    let admin_password_hash = env::var("ADMIN_PASSWORD").expect("ADMIN_PASSWORD must be set");
-
-    // Helper function to create error response
-    async fn create_error_response(state: &AppState, error_msg: &str) -> Html<String> {
-        let reloader_guard = state.reloader.lock().await;
-        let env = reloader_guard.acquire_env().unwrap();
-        let tmpl = env.get_template("login.html").unwrap();
-        let rendered = tmpl
-            .render(context! {
-                error => error_msg
-            })
-            .unwrap();
-        Html(rendered)
-    }
+    let ip = addr.ip().to_string();
+    let user_agent = user_agent(&headers);
 
-    // Parse the stored hash
-    let parsed_hash = match PasswordHash::new(&admin_password_hash) {
-        Ok(hash) => hash,
-        Err(_) => {
-            return Err(create_error_response(&state, "Invalid password hash configuration").await);
+    // Refuse without checking the password while locked out, so guessing gets nowhere
+    match lockout_remaining(&state.pool, &form.username, Some(&ip)).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            record_login(
+                &state.pool,
+                &form.username,
+                None,
+                Some(&ip),
+                user_agent,
+                LoginOutcome::LockedOut,
+            )
+            .await;
+            return Err(login_page(&state, &lockout_message(seconds), false).await);
         }
+        Err(_) => return Err(login_page(&state, "Internal server error", false).await),
+    }
+
+    let user = match find_user_by_username(&state.pool, &form.username).await {
+        Ok(user) => user,
+        Err(_) => return Err(login_page(&state, "Internal server error", false).await),
     };
 
-    // Verify the provided password against the prehashed password
-    let password_matches = Argon2::default()
-        .verify_password(form.password.as_bytes(), &parsed_hash)
-        .is_ok();
-
-    if form.username == admin_username && password_matches {
-        let expiration = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as usize
-            + 3600;
-
-        let claims = Claims {
-            sub: form.username,
-            exp: expiration,
-        };
-
-        let token = match encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(state.jwt_secret.as_bytes()),
-        ) {
-            Ok(token) => token,
-            Err(_) => return Err(create_error_response(&state, "Internal server error").await),
-        };
-
-        let response = Response::builder()
-            .status(StatusCode::FOUND)
-            .header(header::LOCATION, "/admin")
-            .body(axum::body::Body::empty())
-            .unwrap();
-
-        let cookie = CookieBuilder::new("auth_token", token)
-            .http_only(true) // Prevents JavaScript access
-            .secure(true) // Ensures the cookie is sent over HTTPS
-            .path("/") // Defines the cookie scope
-            .max_age(Duration::seconds(3600)) // Matches JWT expiration
-            .expires(OffsetDateTime::now_utc() + Duration::seconds(3600)) // Sets the expiration time
-            .build();
-
-        cookies.add(cookie);
-        Ok(response)
-    } else {
-        Err(create_error_response(&state, "Invalid username or password").await)
+    // Verify the provided password against the user's stored hash
+    let Some((user, _)) = user
+        .as_ref()
+        .filter(|(_, password_hash)| verify_password(&form.password, password_hash))
+    else {
+        let user_id = user.map(|(user, _)| user.id);
+        record_login(
+            &state.pool,
+            &form.username,
+            user_id,
+            Some(&ip),
+            user_agent,
+            LoginOutcome::BadPassword,
+        )
+        .await;
+        return Err(login_page(&state, "Invalid username or password", false).await);
+    };
+    if user.disabled {
+        record_login(
+            &state.pool,
+            &form.username,
+            Some(user.id),
+            Some(&ip),
+            user_agent,
+            LoginOutcome::Disabled,
+        )
+        .await;
+        return Err(login_page(&state, "This account has been disabled", false).await);
     }
+
+    let two_factor = match db::get_two_factor(&state.pool, user.id).await {
+        Ok(two_factor) => two_factor,
+        Err(_) => return Err(login_page(&state, "Internal server error", false).await),
+    };
+    if two_factor.is_some_and(|two_factor| two_factor.enabled) {
+        // The password checked out; remember who for the code step
+        if issue_two_factor_challenge(&cookies, &state.jwt_secret, user.id).is_err() {
+            return Err(login_page(&state, "Internal server error", false).await);
+        }
+        return Err(login_page(&state, "", true).await);
+    }
+
+    if start_session(&cookies, &state, user.id, Some(&ip), user_agent)
+        .await
+        .is_err()
+    {
+        return Err(login_page(&state, "Internal server error", false).await);
+    }
+    record_login(
+        &state.pool,
+        &user.username,
+        Some(user.id),
+        Some(&ip),
+        user_agent,
+        LoginOutcome::Success,
+    )
+    .await;
+    Ok(redirect_to_admin())
 }
 
-/// Handles logout by removing the auth_token cookie and redirecting to the login page.
-pub async fn logout_handler(
-    State(_state): State<Arc<AppState>>,
+/// Second login step for users with two-factor authentication: accepts either a
+/// code from their authenticator app or one of their unused recovery codes.
+pub async fn login_two_factor_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     cookies: Cookies,
-) -> Redirect {
-    // Remove the auth_token cookie
-    cookies.remove(Cookie::build("auth_token").build());
+    Form(form): Form<TwoFactorForm>,
+) -> Result<Response<axum::body::Body>, Html<String>> {
+    let Some(user_id) = two_factor_challenge_user(&cookies, &state.jwt_secret) else {
+        return Err(login_page(&state, "Your login has expired, please sign in again", false).await);
+    };
+    let username = match db::get_user(&state.pool, user_id).await {
+        Ok(Some(user)) if !user.disabled => user.username,
+        Ok(_) => return Err(login_page(&state, "Invalid username or password", false).await),
+        Err(_) => return Err(login_page(&state, "Internal server error", true).await),
+    };
+    let ip = addr.ip().to_string();
+    let user_agent = user_agent(&headers);
+
+    // Wrong codes count towards the same lockout as wrong passwords
+    match lockout_remaining(&state.pool, &username, Some(&ip)).await {
+        Ok(None) => {}
+        Ok(Some(seconds)) => {
+            record_login(
+                &state.pool,
+                &username,
+                Some(user_id),
+                Some(&ip),
+                user_agent,
+                LoginOutcome::LockedOut,
+            )
+            .await;
+            return Err(login_page(&state, &lockout_message(seconds), true).await);
+        }
+        Err(_) => return Err(login_page(&state, "Internal server error", true).await),
+    }
+
+    let two_factor = match db::get_two_factor(&state.pool, user_id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => two_factor,
+        Ok(_) => return Err(login_page(&state, "Invalid username or password", false).await),
+        Err(_) => return Err(login_page(&state, "Internal server error", true).await),
+    };
+
+    let accepted = match two_factor
+        .secret
+        .as_deref()
+        .and_then(|secret| verify_code(secret, &form.code))
+    {
+        Some(step) => db::use_totp_step(&state.pool, user_id, step).await,
+        None => db::use_recovery_code(&state.pool, user_id, &hash_recovery_code(&form.code)).await,
+    };
+    match accepted {
+        Ok(true) => {}
+        Ok(false) => {
+            record_login(
+                &state.pool,
+                &username,
+                Some(user_id),
+                Some(&ip),
+                user_agent,
+                LoginOutcome::BadCode,
+            )
+            .await;
+            return Err(login_page(&state, "Invalid code", true).await);
+        }
+        Err(_) => return Err(login_page(&state, "Internal server error", true).await),
+    }
+
+    cookies.remove(Cookie::build("two_factor_challenge").path("/").build());
+    if start_session(&cookies, &state, user_id, Some(&ip), user_agent)
+        .await
+        .is_err()
+    {
+        return Err(login_page(&state, "Internal server error", false).await);
+    }
+    record_login(
+        &state.pool,
+        &username,
+        Some(user_id),
+        Some(&ip),
+        user_agent,
+        LoginOutcome::Success,
+    )
+    .await;
+    Ok(redirect_to_admin())
+}
+
+/// Renders the login page, showing the code form instead of the password form
+/// if `two_factor` is set.
+pub async fn login_page(state: &AppState, error_msg: &str, two_factor: bool) -> Html<String> {
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("login.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            error => (!error_msg.is_empty()).then_some(error_msg),
+            two_factor => two_factor,
+            sso_provider => state.oidc.as_ref().map(|oidc| oidc.provider_name.as_str()),
+        })
+        .unwrap();
+    Html(rendered)
+}
+
+pub fn redirect_to_admin() -> Response<axum::body::Body> {
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, "/admin")
+        .body(axum::body::Body::empty())
+        .unwrap()
+}
+
+pub fn user_agent(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Sets the cookie that lets the user finish logging in with a two-factor code.
+pub fn issue_two_factor_challenge(
+    cookies: &Cookies,
+    jwt_secret: &str,
+    user_id: i64,
+) -> Result<(), jsonwebtoken::errors::Error> {
+    let expiration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize
+        + TWO_FACTOR_CHALLENGE_DURATION as usize;
+
+    let claims = TwoFactorChallengeClaims {
+        sub: user_id.to_string(),
+        aud: TWO_FACTOR_AUDIENCE.to_string(),
+        exp: expiration,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?;
+
+    let cookie = CookieBuilder::new("two_factor_challenge", token)
+        .http_only(true)
+        .secure(true)
+        .path("/")
+        .max_age(Duration::seconds(TWO_FACTOR_CHALLENGE_DURATION))
+        .expires(OffsetDateTime::now_utc() + Duration::seconds(TWO_FACTOR_CHALLENGE_DURATION))
+        .build();
+    cookies.add(cookie);
+    Ok(())
+}
+
+/// Returns the user who passed the first login step for the pending two-factor challenge.
+fn two_factor_challenge_user(cookies: &Cookies, jwt_secret: &str) -> Option<i64> {
+    let token = cookies.get("two_factor_challenge")?;
+    let mut validation = Validation::default();
+    validation.set_audience(&[TWO_FACTOR_AUDIENCE]);
+    let claims = decode::<TwoFactorChallengeClaims>(
+        token.value(),
+        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &validation,
+    )
+    .ok()?
+    .claims;
+
+    claims.sub.parse().ok()
+}
+
+/// Handles logout by revoking the session, removing its cookies and redirecting to
+/// the login page.
+pub async fn logout_handler(State(state): State<Arc<AppState>>, cookies: Cookies) -> Redirect {
+    if let Some(user) = current_user(&cookies, &state).await {
+        if let Some(session_id) = user.session_id {
+            if let Err(e) = db::revoke_session(&state.pool, session_id, user.id).await {
+                eprintln!("Failed to revoke session: {}", e);
+            }
+        }
+    }
+    clear_session_cookies(&cookies);
     // Redirect to the login page
     Redirect::to("/login")
-}
\ No newline at end of file
+}
@@ -0,0 +1,53 @@
+use crate::{
+    db,
+    types::{AppState, CurrentUser, Role},
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use minijinja::context;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// How many login attempts the page lists.
+const LOGIN_EVENTS_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct LoginEventsQuery {
+    username: Option<String>,
+    ip: Option<String>,
+}
+
+/// Lists recent login attempts, optionally only for one username or IP address.
+pub async fn login_events_page_handler(
+    Query(query): Query<LoginEventsQuery>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let username = query.username.as_deref().filter(|username| !username.is_empty());
+    let ip = query.ip.as_deref().filter(|ip| !ip.is_empty());
+    let events = match db::get_login_events(&state.pool, username, ip, LOGIN_EVENTS_PAGE_SIZE).await
+    {
+        Ok(events) => events,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("login_events.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            events => events,
+            username => username,
+            ip => ip,
+            current_user => user,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
@@ -1,4 +1,18 @@
 pub mod home;
 pub mod admin;
+pub mod api_tokens;
 pub mod login;
-pub mod album;
\ No newline at end of file
+pub mod login_events;
+pub mod oidc;
+pub mod album;
+pub mod browse;
+pub mod share;
+pub mod sessions;
+pub mod search;
+pub mod settings;
+pub mod smart_albums;
+pub mod tags;
+pub mod uploads;
+pub mod users;
+pub mod permissions;
+pub mod two_factor;
\ No newline at end of file
@@ -0,0 +1,216 @@
+use crate::auth::login_throttle::record_login;
+use crate::auth::oidc::{authorization_request, exchange_code, AuthorizationRequest};
+use crate::auth::sessions::start_session;
+use crate::db;
+use crate::handlers::login::{
+    issue_two_factor_challenge, login_page, redirect_to_admin, user_agent,
+};
+use crate::types::{AppState, LoginOutcome, OidcLoginClaims};
+use axum::http::{HeaderMap, StatusCode};
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_cookies::cookie::time::{Duration, OffsetDateTime};
+use tower_cookies::cookie::{CookieBuilder, SameSite};
+use tower_cookies::{Cookie, Cookies};
+
+/// Audience of the single sign-on login cookie, see `OidcLoginClaims`.
+const OIDC_LOGIN_AUDIENCE: &str = "oidc-login";
+/// How long the user has to sign in at the identity provider, in seconds.
+const OIDC_LOGIN_DURATION: i64 = 600;
+const OIDC_LOGIN_COOKIE: &str = "oidc_login";
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Starts a single sign-on login by sending the user to the identity provider.
+pub async fn oidc_login_handler(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+) -> Response {
+    let Some(config) = &state.oidc else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let request = match authorization_request(config).await {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("Failed to start single sign-on: {}", e);
+            return login_page(&state, "Single sign-on is unavailable right now", false)
+                .await
+                .into_response();
+        }
+    };
+    if issue_oidc_login_cookie(&cookies, &state.jwt_secret, &request).is_err() {
+        return login_page(&state, "Internal server error", false)
+            .await
+            .into_response();
+    }
+
+    Redirect::to(&request.url).into_response()
+}
+
+/// Where the identity provider sends the user back to. Logs them in as the user
+/// their account is linked to, if any, asking for their two-factor code first if
+/// they have it turned on, like a password login does.
+pub async fn oidc_callback_handler(
+    Query(query): Query<OidcCallbackQuery>,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    cookies: Cookies,
+) -> Response {
+    let Some(config) = &state.oidc else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let login = oidc_login_claims(&cookies, &state.jwt_secret);
+    cookies.remove(Cookie::build(OIDC_LOGIN_COOKIE).path("/").build());
+    if let Some(error) = query.error {
+        eprintln!("Single sign-on failed: {}", error);
+        return login_page(&state, "Single sign-on was cancelled or failed", false)
+            .await
+            .into_response();
+    }
+    // The state ties the callback to a login started from this browser
+    let (Some(login), Some(code)) = (login, query.code) else {
+        return login_page(&state, "Your login has expired, please sign in again", false)
+            .await
+            .into_response();
+    };
+    if query.state.as_deref() != Some(login.state.as_str()) {
+        return login_page(&state, "Your login has expired, please sign in again", false)
+            .await
+            .into_response();
+    }
+
+    let claims = match exchange_code(config, &code, &login.code_verifier, &login.nonce).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            eprintln!("Single sign-on failed: {}", e);
+            return login_page(&state, "Single sign-on failed", false)
+                .await
+                .into_response();
+        }
+    };
+
+    let email = claims.verified_email();
+    let user = match db::find_oidc_user(&state.pool, &claims.sub, email.as_deref()).await {
+        Ok(Some(user_id)) => db::get_user(&state.pool, user_id).await,
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
+    };
+    let user = match user {
+        Ok(Some(user)) if !user.disabled => user,
+        Ok(_) => {
+            let message = format!(
+                "Your {} account isn't allowed to sign in here",
+                config.provider_name
+            );
+            return login_page(&state, &message, false).await.into_response();
+        }
+        Err(_) => {
+            return login_page(&state, "Internal server error", false)
+                .await
+                .into_response()
+        }
+    };
+
+    let two_factor = match db::get_two_factor(&state.pool, user.id).await {
+        Ok(two_factor) => two_factor,
+        Err(_) => {
+            return login_page(&state, "Internal server error", false)
+                .await
+                .into_response()
+        }
+    };
+    if two_factor.is_some_and(|two_factor| two_factor.enabled) {
+        if issue_two_factor_challenge(&cookies, &state.jwt_secret, user.id).is_err() {
+            return login_page(&state, "Internal server error", false)
+                .await
+                .into_response();
+        }
+        return login_page(&state, "", true).await.into_response();
+    }
+
+    let ip = addr.ip().to_string();
+    let user_agent = user_agent(&headers);
+    if start_session(&cookies, &state, user.id, Some(&ip), user_agent)
+        .await
+        .is_err()
+    {
+        return login_page(&state, "Internal server error", false)
+            .await
+            .into_response();
+    }
+    record_login(
+        &state.pool,
+        &user.username,
+        Some(user.id),
+        Some(&ip),
+        user_agent,
+        LoginOutcome::Success,
+    )
+    .await;
+    redirect_to_admin()
+}
+
+fn issue_oidc_login_cookie(
+    cookies: &Cookies,
+    jwt_secret: &str,
+    request: &AuthorizationRequest,
+) -> Result<(), jsonwebtoken::errors::Error> {
+    let expiration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize
+        + OIDC_LOGIN_DURATION as usize;
+
+    let claims = OidcLoginClaims {
+        state: request.state.clone(),
+        nonce: request.nonce.clone(),
+        code_verifier: request.code_verifier.clone(),
+        aud: OIDC_LOGIN_AUDIENCE.to_string(),
+        exp: expiration,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?;
+
+    let cookie = CookieBuilder::new(OIDC_LOGIN_COOKIE, token)
+        .http_only(true)
+        .secure(true)
+        // Sent along when the identity provider redirects back here
+        .same_site(SameSite::Lax)
+        .path("/")
+        .max_age(Duration::seconds(OIDC_LOGIN_DURATION))
+        .expires(OffsetDateTime::now_utc() + Duration::seconds(OIDC_LOGIN_DURATION))
+        .build();
+    cookies.add(cookie);
+    Ok(())
+}
+
+fn oidc_login_claims(cookies: &Cookies, jwt_secret: &str) -> Option<OidcLoginClaims> {
+    let token = cookies.get(OIDC_LOGIN_COOKIE)?;
+    let mut validation = Validation::default();
+    validation.set_audience(&[OIDC_LOGIN_AUDIENCE]);
+    decode::<OidcLoginClaims>(
+        token.value(),
+        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &validation,
+    )
+    .ok()
+    .map(|data| data.claims)
+}
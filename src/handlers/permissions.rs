@@ -0,0 +1,110 @@
+use crate::{
+    auth::permissions::require_album_permission,
+    db,
+    types::{AlbumPermission, ApiScope, AppState, CurrentUser, GrantPermissionRequest},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Lists who has been granted what on an album, along with the users that
+/// permissions can be granted to.
+pub async fn list_album_permissions_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsRead) {
+        return forbidden.into_response();
+    }
+
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Owner).await
+    {
+        return denied.into_response();
+    }
+
+    let grants = match db::get_album_grants(&state.pool, album_id).await {
+        Ok(grants) => grants,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let users = match db::get_users(&state.pool).await {
+        Ok(users) => users,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    Json(json!({
+        "status": "success",
+        "permissions": grants,
+        "users": users
+            .iter()
+            .filter(|u| u.id != user.id)
+            .map(|u| json!({ "id": u.id, "username": u.username }))
+            .collect::<Vec<_>>(),
+    }))
+    .into_response()
+}
+
+/// Grants a user view, upload or edit permission on an album.
+pub async fn grant_album_permission_handler(
+    Path((album_id, user_id)): Path<(i64, i64)>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<GrantPermissionRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Owner).await
+    {
+        return denied.into_response();
+    }
+
+    // Ownership comes from creating the album, it can't be granted
+    if request.permission == AlbumPermission::Owner {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Permission must be view, upload or edit",
+        )
+            .into_response();
+    }
+
+    match db::get_user(&state.pool, user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    match db::set_album_permission(&state.pool, album_id, user_id, request.permission).await {
+        Ok(()) => Json(json!({ "status": "success" })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn revoke_album_permission_handler(
+    Path((album_id, user_id)): Path<(i64, i64)>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Owner).await
+    {
+        return denied.into_response();
+    }
+
+    match db::remove_album_permission(&state.pool, album_id, user_id).await {
+        Ok(()) => Json(json!({ "status": "success" })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
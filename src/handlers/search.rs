@@ -0,0 +1,130 @@
+use crate::{
+    db,
+    types::{AlbumSearchHit, AppState, ImageSearchHit},
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Most albums and images returned for one search.
+const ALBUM_RESULT_LIMIT: i64 = 24;
+const IMAGE_RESULT_LIMIT: i64 = 100;
+/// Most words of a query used, to keep queries cheap.
+const MAX_QUERY_TERMS: usize = 10;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+/// Search results. Highlighted fields are HTML with the matched words in `<mark>`.
+#[derive(Serialize)]
+pub struct SearchResults {
+    query: String,
+    albums: Vec<AlbumSearchHit>,
+    images: Vec<ImageSearchHit>,
+}
+
+pub async fn search_page_handler(
+    Query(query): Query<SearchQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let results = match search(&state, &query.q).await {
+        Ok(results) => results,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("search.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            query => results.query,
+            albums => results.albums,
+            images => results.images,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+pub async fn search_api_handler(
+    Query(query): Query<SearchQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match search(&state, &query.q).await {
+        Ok(results) => Json(results).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Searches public albums and their images, best matches first.
+async fn search(state: &AppState, query: &str) -> Result<SearchResults, sqlx::Error> {
+    let query = query.trim().to_string();
+    let Some(fts_query) = fts_query(&query) else {
+        return Ok(SearchResults {
+            query,
+            albums: Vec::new(),
+            images: Vec::new(),
+        });
+    };
+
+    let mut albums = db::search_albums(&state.pool, &fts_query, ALBUM_RESULT_LIMIT).await?;
+    for album in &mut albums {
+        album.name = highlight_html(&album.name);
+        album.description = album.description.as_deref().map(highlight_html);
+    }
+    let mut images = db::search_images(&state.pool, &fts_query, IMAGE_RESULT_LIMIT).await?;
+    for image in &mut images {
+        image.album_name = escape_html(&image.album_name);
+        for field in [
+            &mut image.title,
+            &mut image.caption,
+            &mut image.keywords,
+            &mut image.equipment,
+        ] {
+            *field = field.as_deref().map(highlight_html);
+        }
+    }
+
+    Ok(SearchResults {
+        query,
+        albums,
+        images,
+    })
+}
+
+/// Turns what someone typed into an FTS5 query matching every word, each also as
+/// the start of a longer word. Punctuation is dropped, so the query can't contain
+/// FTS5 syntax.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .take(MAX_QUERY_TERMS)
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Escapes text from the search index for HTML, turning the match markers that
+/// `db::search_albums` and `db::search_images` add into `<mark>` tags.
+fn highlight_html(text: &str) -> String {
+    escape_html(text)
+        .replace('\u{1}', "<mark>")
+        .replace('\u{2}', "</mark>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
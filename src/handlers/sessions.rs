@@ -0,0 +1,56 @@
+use crate::{
+    auth::sessions::clear_session_cookies,
+    db,
+    types::{AppState, CurrentUser},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tower_cookies::Cookies;
+
+/// Logs one of the logged-in user's sessions out, e.g. on a lost device.
+pub async fn revoke_session_handler(
+    Path(session_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    user: CurrentUser,
+) -> Response {
+    match db::revoke_session(&state.pool, session_id, user.id).await {
+        Ok(true) => {
+            if user.session_id == Some(session_id) {
+                clear_session_cookies(&cookies);
+            }
+            Json(json!({
+                "status": "success",
+                "message": "Session logged out",
+            }))
+            .into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Session not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Logs the logged-in user out everywhere, including this browser.
+pub async fn revoke_all_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    user: CurrentUser,
+) -> Response {
+    match db::revoke_user_sessions(&state.pool, user.id).await {
+        Ok(()) => {
+            clear_session_cookies(&cookies);
+            Json(json!({
+                "status": "success",
+                "message": "Logged out everywhere",
+            }))
+            .into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    db,
+    types::{AppState, ContactLink, CurrentUser, Role, SiteSettings},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Most contact links shown on the home page.
+const MAX_CONTACT_LINKS: usize = 10;
+/// Longest title, tagline, owner name or link label accepted.
+const MAX_TEXT_LENGTH: usize = 200;
+
+pub async fn settings_page_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let settings = match serde_json::to_string(&*state.settings.read().unwrap()) {
+        Ok(settings) => settings,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("settings.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            settings => settings,
+            current_user => user,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+/// Saves the site's branding. Every page picks the change up on its next render.
+pub async fn update_settings_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<SiteSettings>,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let settings = match validate_settings(request) {
+        Ok(settings) => settings,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    if let Err(e) = db::update_site_settings(&state.pool, &settings).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    *state.settings.write().unwrap() = settings;
+
+    Json(json!({"status": "success"})).into_response()
+}
+
+/// Trims the settings and checks them. URLs and the colour end up in `href`s and
+/// inline CSS, so only safe forms of them are accepted.
+fn validate_settings(settings: SiteSettings) -> Result<SiteSettings, String> {
+    let site_title = settings.site_title.trim().to_string();
+    if site_title.is_empty() {
+        return Err("Site title can't be empty".to_string());
+    }
+    let tagline = non_empty(settings.tagline);
+    let owner_name = non_empty(settings.owner_name);
+    let too_long = [Some(&site_title), tagline.as_ref(), owner_name.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|text| text.chars().count() > MAX_TEXT_LENGTH);
+    if too_long {
+        return Err(format!("Text can be at most {} characters", MAX_TEXT_LENGTH));
+    }
+
+    let mut contact_links = Vec::new();
+    for link in settings.contact_links {
+        let label = link.label.trim().to_string();
+        let url = link.url.trim().to_string();
+        if label.is_empty() && url.is_empty() {
+            continue;
+        }
+        if label.is_empty() {
+            return Err(format!("Link to {} needs a label", url));
+        }
+        if label.chars().count() > MAX_TEXT_LENGTH {
+            return Err(format!("Text can be at most {} characters", MAX_TEXT_LENGTH));
+        }
+        if !["https://", "http://", "mailto:"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            return Err(format!(
+                "Link '{}' must start with https://, http:// or mailto:",
+                label
+            ));
+        }
+        contact_links.push(ContactLink { label, url });
+    }
+    if contact_links.len() > MAX_CONTACT_LINKS {
+        return Err(format!("At most {} contact links are allowed", MAX_CONTACT_LINKS));
+    }
+
+    let background_image = non_empty(settings.background_image);
+    if let Some(image) = &background_image {
+        let allowed_start = (image.starts_with('/') && !image.starts_with("//"))
+            || image.starts_with("https://")
+            || image.starts_with("http://");
+        let has_unsafe_chars = image
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "'\"()\\<>".contains(c));
+        if !allowed_start || has_unsafe_chars {
+            return Err(
+                "Background image must be a path like /static/bg.webp or an http(s) URL"
+                    .to_string(),
+            );
+        }
+    }
+
+    let accent_color = settings.accent_color.trim().to_lowercase();
+    let is_hex_color = accent_color.len() == 7
+        && accent_color.starts_with('#')
+        && accent_color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex_color {
+        return Err("Accent colour must look like #34d399".to_string());
+    }
+
+    Ok(SiteSettings {
+        site_title,
+        tagline,
+        owner_name,
+        contact_links,
+        background_image,
+        accent_color,
+    })
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
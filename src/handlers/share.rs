@@ -0,0 +1,135 @@
+use crate::{
+    auth::{album_access::grant_album_access, permissions::require_album_permission},
+    db,
+    types::{AlbumPermission, ApiScope, AppState, CreateShareLinkRequest, CurrentUser},
+    utils::{generate_token, hash_token},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+use tower_cookies::Cookies;
+
+/// Opens a share link: counts the view and unlocks the linked album for this visitor.
+pub async fn share_link_handler(
+    Path(token): Path<String>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+) -> Response {
+    let link = match db::use_share_link(&state.pool, &hash_token(&token)).await {
+        Ok(Some(link)) => link,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                "This link is invalid, has expired or has been revoked",
+            )
+                .into_response()
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    if grant_album_access(&cookies, &state.jwt_secret, link.album_id, Some(link.id)).is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response();
+    }
+    Redirect::to(&format!("/albums/{}", link.album_id)).into_response()
+}
+
+pub async fn list_share_links_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsRead) {
+        return forbidden.into_response();
+    }
+
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+
+    match db::get_share_links(&state.pool, album_id).await {
+        Ok(links) => Json(json!({
+            "status": "success",
+            "share_links": links,
+        }))
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn create_share_link_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<CreateShareLinkRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+
+    if request.expires_in_days.is_some_and(|days| days <= 0)
+        || request.max_views.is_some_and(|views| views <= 0)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Expiry and view limit must be positive",
+        )
+            .into_response();
+    }
+
+    // Only the hash is stored, so the link can't be shown again after this response
+    let token = generate_token();
+    match db::create_share_link(&state.pool, album_id, &hash_token(&token), &request).await {
+        Ok(id) => Json(json!({
+            "status": "success",
+            "id": id,
+            "url": format!("/s/{}", token),
+        }))
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Revokes a share link, which also locks out visitors who already opened it.
+pub async fn revoke_share_link_handler(
+    Path(link_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+
+    let album_id = match db::get_share_link_album(&state.pool, link_id).await {
+        Ok(Some(album_id)) => album_id,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Share link not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+
+    match db::revoke_share_link(&state.pool, link_id).await {
+        Ok(true) => Json(json!({
+            "status": "success",
+            "message": "Share link revoked",
+        }))
+        .into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Share link not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    db,
+    handlers::browse::{parse_date, parse_tags},
+    types::{
+        Album, ApiScope, AppState, CurrentUser, FilteredImage, Role, SmartAlbumRequest,
+        Visibility,
+    },
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Shows a smart album like any other album, with the images that match its filter
+/// now, newest first.
+pub async fn smart_album_handler(
+    Path(smart_album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let smart_album = match db::get_smart_album(&state.pool, smart_album_id).await {
+        Ok(Some(smart_album)) => smart_album,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let matches = match db::filter_images(&state.pool, &smart_album.filter).await {
+        Ok(matches) => matches,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let image_ids: Vec<i64> = matches.iter().map(|image| image.id).collect();
+    let images = match db::get_images(&state.pool, &image_ids).await {
+        Ok(images) => images,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let images = match db::with_image_tags(&state.pool, images).await {
+        Ok(images) => images,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // Dated by its newest photo, with the camera and lens most of its photos were
+    // taken with, the way uploaded albums are
+    let date = matches
+        .iter()
+        .find_map(|image| image.taken_at.as_deref())
+        .unwrap_or(&smart_album.created_at);
+    let album = Album {
+        id: smart_album.id,
+        date: date.get(..10).unwrap_or(date).to_string(),
+        name: smart_album.name,
+        description: smart_album.description,
+        num_images: matches.len() as i32,
+        camera_model: most_common(&matches, |image| image.camera_model.as_deref()),
+        lens_model: most_common(&matches, |image| image.lens_model.as_deref()),
+        aperture: most_common(&matches, |image| image.aperture.as_deref()),
+        allow_download: false,
+        visibility: Visibility::Public,
+    };
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("album.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            album => album,
+            images => images,
+            can_download => false,
+            // Only images in public albums match, so their URLs are never signed
+            image_query => "",
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+pub async fn list_smart_albums_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsRead) {
+        return forbidden.into_response();
+    }
+
+    match db::get_smart_album_summaries(&state.pool).await {
+        Ok(smart_albums) => {
+            Json(json!({"status": "success", "smart_albums": smart_albums})).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn create_smart_album_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<SmartAlbumRequest>,
+) -> Response {
+    if let Err(forbidden) = require_editor(&user) {
+        return forbidden.into_response();
+    }
+    let request = match check_request(request) {
+        Ok(request) => request,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+
+    match db::create_smart_album(&state.pool, &request).await {
+        Ok(smart_album_id) => {
+            Json(json!({"status": "success", "smart_album_id": smart_album_id})).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn update_smart_album_handler(
+    Path(smart_album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<SmartAlbumRequest>,
+) -> Response {
+    if let Err(forbidden) = require_editor(&user) {
+        return forbidden.into_response();
+    }
+    let request = match check_request(request) {
+        Ok(request) => request,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+
+    match db::update_smart_album(&state.pool, smart_album_id, &request).await {
+        Ok(true) => Json(json!({"status": "success"})).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Smart album not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn delete_smart_album_handler(
+    Path(smart_album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = require_editor(&user) {
+        return forbidden.into_response();
+    }
+
+    match db::delete_smart_album(&state.pool, smart_album_id).await {
+        Ok(true) => Json(json!({"status": "success"})).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Smart album not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Smart albums only show images from public albums, so anyone who can create
+/// albums can manage all of them.
+fn require_editor(user: &CurrentUser) -> Result<(), (StatusCode, &'static str)> {
+    user.require_scope(ApiScope::AlbumsWrite)?;
+    user.require(Role::Editor)
+}
+
+/// Checks a smart album's name and filter, tidying them up for storing.
+fn check_request(mut request: SmartAlbumRequest) -> Result<SmartAlbumRequest, String> {
+    request.name = request.name.trim().to_string();
+    if request.name.is_empty() {
+        return Err("Smart albums need a name".to_string());
+    }
+    request.description = non_empty(request.description);
+
+    let filter = &mut request.filter;
+    filter.camera = non_empty(filter.camera.take());
+    filter.lens = non_empty(filter.lens.take());
+    filter.date_from = non_empty(filter.date_from.take());
+    filter.date_to = non_empty(filter.date_to.take());
+    for (name, date) in [("date_from", &filter.date_from), ("date_to", &filter.date_to)] {
+        if let Some(date) = date.as_deref().filter(|date| parse_date(date).is_none()) {
+            return Err(format!("Invalid {}: '{}'", name, date));
+        }
+    }
+    if let Some(rating) = filter.rating_min.filter(|rating| !(1..=5).contains(rating)) {
+        return Err(format!("Invalid rating_min: '{}'", rating));
+    }
+    // Tags may be given by name, but are matched by slug
+    filter.tags = parse_tags(&filter.tags.join(","));
+
+    Ok(request)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// The value most of the images have, ties going to the newest image's.
+fn most_common(
+    images: &[FilteredImage],
+    value: fn(&FilteredImage) -> Option<&str>,
+) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for image in images {
+        if let Some(value) = value(image) {
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+    // `max_by_key` picks the last of equal values, so look from the oldest
+    images
+        .iter()
+        .rev()
+        .filter_map(value)
+        .max_by_key(|value| counts[value])
+        .map(str::to_string)
+}
@@ -0,0 +1,176 @@
+use crate::{
+    auth::permissions::require_album_permission,
+    db,
+    types::{
+        AlbumPermission, AlbumTagsRequest, ApiScope, AppState, CurrentUser, ImageTagsRequest,
+        Tag,
+    },
+    utils::tag_slug,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Longest tag name accepted.
+const MAX_TAG_LENGTH: usize = 50;
+/// Most tags that can be set or changed in one request.
+const MAX_TAGS_PER_REQUEST: usize = 50;
+
+/// Shows the images in public albums with a tag, across albums, and the public
+/// albums that have it.
+pub async fn tag_page_handler(
+    Path(slug): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let tag = match db::get_tag_by_slug(&state.pool, &slug).await {
+        Ok(Some(tag)) => tag,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let albums = match db::get_tagged_albums(&state.pool, tag.id).await {
+        Ok(albums) => albums,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let images = match db::get_tagged_images(&state.pool, tag.id).await {
+        Ok(images) => images,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let images = match db::with_image_tags(&state.pool, images).await {
+        Ok(images) => images,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("tag.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            tag => tag,
+            albums => albums,
+            images => images,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+/// Replaces the tags of an album itself, as opposed to those of its images.
+pub async fn update_album_tags_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<AlbumTagsRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+
+    let tags = match get_or_create_tags(&state, &request.tags).await {
+        Ok(tags) => tags,
+        Err(error) => return error.into_response(),
+    };
+    let tag_ids: Vec<i64> = tags.iter().map(|tag| tag.id).collect();
+    if let Err(e) = db::set_album_tags(&state.pool, album_id, &tag_ids).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    Json(json!({"status": "success", "tags": tags})).into_response()
+}
+
+/// Adds tags to and removes tags from the selected images of an album. Image ids
+/// from other albums are ignored.
+pub async fn update_image_tags_handler(
+    Path(album_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<ImageTagsRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require_scope(ApiScope::AlbumsWrite) {
+        return forbidden.into_response();
+    }
+    if let Err(denied) =
+        require_album_permission(&state.pool, &user, album_id, AlbumPermission::Edit).await
+    {
+        return denied.into_response();
+    }
+    if request.image_ids.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No images selected").into_response();
+    }
+
+    let added = match get_or_create_tags(&state, &request.add).await {
+        Ok(tags) => tags,
+        Err(error) => return error.into_response(),
+    };
+    for tag in &added {
+        let result = db::add_image_tag(&state.pool, album_id, &request.image_ids, tag.id).await;
+        if let Err(e) = result {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+    for name in &request.remove {
+        let tag = match db::get_tag_by_slug(&state.pool, &tag_slug(name)).await {
+            Ok(Some(tag)) => tag,
+            Ok(None) => continue,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let result = db::remove_image_tag(&state.pool, album_id, &request.image_ids, tag.id).await;
+        if let Err(e) = result {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+    if let Err(e) = db::reindex_images(&state.pool, album_id, &request.image_ids).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    Json(json!({"status": "success"})).into_response()
+}
+
+/// Checks tag names and finds or creates their tags.
+async fn get_or_create_tags(
+    state: &AppState,
+    names: &[String],
+) -> Result<Vec<Tag>, (StatusCode, String)> {
+    if names.len() > MAX_TAGS_PER_REQUEST {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("At most {} tags can be changed at once", MAX_TAGS_PER_REQUEST),
+        ));
+    }
+
+    // Check every name before creating any of the tags
+    for name in names {
+        let name = name.trim();
+        if name.chars().count() > MAX_TAG_LENGTH {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Tags can be at most {} characters", MAX_TAG_LENGTH),
+            ));
+        }
+        if tag_slug(name).is_empty() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Tag '{}' needs a letter or digit", name),
+            ));
+        }
+    }
+
+    let mut tags: Vec<Tag> = Vec::new();
+    for name in names {
+        match db::get_or_create_tag(&state.pool, name).await {
+            Ok(Some(tag)) if !tags.iter().any(|existing| existing.id == tag.id) => tags.push(tag),
+            Ok(_) => {}
+            Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+    Ok(tags)
+}
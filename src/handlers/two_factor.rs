@@ -0,0 +1,204 @@
+use crate::{
+    auth::{
+        password::verify_password,
+        two_factor::{
+            generate_recovery_codes, generate_secret, hash_recovery_code, otpauth_uri,
+            qr_code_svg, verify_code,
+        },
+    },
+    db,
+    types::{AppState, ConfirmPasswordRequest, CurrentUser, TwoFactorCodeRequest},
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Shows whether two-factor authentication is on for the logged-in user and lets
+/// them set it up or turn it off, along with the sessions they're logged in with.
+pub async fn security_page_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    let enabled = match db::get_two_factor(&state.pool, user.id).await {
+        Ok(two_factor) => two_factor.is_some_and(|two_factor| two_factor.enabled),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let recovery_codes_left = if enabled {
+        db::count_unused_recovery_codes(&state.pool, user.id)
+            .await
+            .unwrap_or_default()
+    } else {
+        0
+    };
+    let sessions = db::get_sessions(&state.pool, user.id)
+        .await
+        .unwrap_or_default();
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("security.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            two_factor_enabled => enabled,
+            recovery_codes_left => recovery_codes_left,
+            sessions => sessions,
+            current_session_id => user.session_id,
+            current_user => user,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+/// Starts enrollment with a new secret. The secret is only ever shown here; it is
+/// used for login once the user confirms a code from it.
+///
+/// Asks for the password first, so a stolen session can't be used to enroll
+/// someone else's authenticator and lock the owner out.
+pub async fn setup_two_factor_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<ConfirmPasswordRequest>,
+) -> Response {
+    if let Err(denied) = confirm_password(&state, &user, &request.password).await {
+        return denied;
+    }
+
+    match db::get_two_factor(&state.pool, user.id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => {
+            return (
+                StatusCode::CONFLICT,
+                "Two-factor authentication is already on",
+            )
+                .into_response()
+        }
+        Ok(_) => {}
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let secret = generate_secret();
+    if let Err(e) = db::set_pending_totp_secret(&state.pool, user.id, &secret).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    let uri = otpauth_uri(&user.username, &secret);
+    Json(json!({
+        "status": "success",
+        "secret": secret,
+        "otpauth_uri": uri,
+        "qr_code": qr_code_svg(&uri),
+    }))
+    .into_response()
+}
+
+/// Finishes enrollment once the user enters a code from their authenticator app,
+/// returning their recovery codes. They can't be shown again.
+pub async fn enable_two_factor_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<TwoFactorCodeRequest>,
+) -> Response {
+    let secret = match db::get_two_factor(&state.pool, user.id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => {
+            return (
+                StatusCode::CONFLICT,
+                "Two-factor authentication is already on",
+            )
+                .into_response()
+        }
+        Ok(Some(two_factor)) => two_factor.secret,
+        Ok(None) => None,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let Some(secret) = secret else {
+        return (StatusCode::BAD_REQUEST, "Start the setup first").into_response();
+    };
+
+    let Some(step) = verify_code(&secret, &request.code) else {
+        return (StatusCode::BAD_REQUEST, "That code doesn't match, try again").into_response();
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    if let Err(e) = db::replace_recovery_codes(&state.pool, user.id, &hashes).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    if let Err(e) = db::enable_two_factor(&state.pool, user.id, step).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+
+    Json(json!({
+        "status": "success",
+        "recovery_codes": recovery_codes,
+    }))
+    .into_response()
+}
+
+/// Replaces the user's recovery codes with a new set, invalidating the old ones.
+pub async fn regenerate_recovery_codes_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<ConfirmPasswordRequest>,
+) -> Response {
+    if let Err(denied) = confirm_password(&state, &user, &request.password).await {
+        return denied;
+    }
+
+    match db::get_two_factor(&state.pool, user.id).await {
+        Ok(Some(two_factor)) if two_factor.enabled => {}
+        Ok(_) => {
+            return (StatusCode::BAD_REQUEST, "Two-factor authentication is off").into_response()
+        }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    match db::replace_recovery_codes(&state.pool, user.id, &hashes).await {
+        Ok(()) => Json(json!({
+            "status": "success",
+            "recovery_codes": recovery_codes,
+        }))
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn disable_two_factor_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<ConfirmPasswordRequest>,
+) -> Response {
+    if let Err(denied) = confirm_password(&state, &user, &request.password).await {
+        return denied;
+    }
+
+    match db::disable_two_factor(&state.pool, user.id).await {
+        Ok(()) => Json(json!({ "status": "success" })).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Checks the password re-entered to confirm a change to the user's second factor.
+async fn confirm_password(
+    state: &AppState,
+    user: &CurrentUser,
+    password: &str,
+) -> Result<(), Response> {
+    match db::find_user_by_username(&state.pool, &user.username).await {
+        Ok(Some((_, hash))) if verify_password(password, &hash) => Ok(()),
+        Ok(_) => Err((StatusCode::FORBIDDEN, "Incorrect password").into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{
+    archive::image_path,
+    auth::signed_urls::verify_image_signature,
+    db::get_album_visibility,
+    types::{AppState, Visibility},
+    utils::ImageQuality,
+};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+#[derive(Deserialize)]
+pub struct SignatureQuery {
+    expires: Option<u64>,
+    sig: Option<String>,
+}
+
+/// Serves an uploaded image. Images of public albums are served to anyone; the
+/// rest need a URL signed by `album_image_query` that hasn't expired yet.
+pub async fn uploads_handler(
+    Path((album_id, quality, filename)): Path<(i64, ImageQuality, String)>,
+    Query(query): Query<SignatureQuery>,
+    State(state): State<Arc<AppState>>,
+    request: Request,
+) -> Response {
+    // Filenames are generated UUIDs, so anything path-like is a traversal attempt
+    if filename.starts_with('.') || filename.contains(['/', '\\']) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let visibility = match get_album_visibility(&state.pool, album_id).await {
+        Ok(Some(visibility)) => visibility,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    if visibility != Visibility::Public {
+        let signed = match (query.expires, query.sig.as_deref()) {
+            (Some(expires), Some(sig)) => {
+                verify_image_signature(&state.url_signing_key, album_id, expires, sig)
+            }
+            _ => false,
+        };
+        if !signed {
+            return (StatusCode::FORBIDDEN, "Invalid or expired image link").into_response();
+        }
+    }
+
+    let path = image_path(album_id, quality, &filename);
+    let mut response = match ServeFile::new(path).oneshot(request).await {
+        Ok(response) => response.into_response(),
+        Err(never) => match never {},
+    };
+    if visibility != Visibility::Public {
+        // Keep shared caches from handing restricted images to other visitors
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    }
+    response
+}
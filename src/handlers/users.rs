@@ -0,0 +1,233 @@
+use crate::{
+    auth::password::hash_password,
+    db,
+    types::{
+        AddOidcIdentityRequest, AppState, CreateUserRequest, CurrentUser, OidcIdentityKind, Role,
+        UpdateUserRequest,
+    },
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use minijinja::context;
+use serde_json::json;
+use std::sync::Arc;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub async fn users_page_handler(State(state): State<Arc<AppState>>, user: CurrentUser) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let users = db::get_users(&state.pool).await.unwrap_or_default();
+    let oidc_identities = db::get_oidc_identities(&state.pool)
+        .await
+        .unwrap_or_default();
+
+    let reloader_guard = state.reloader.lock().await;
+    let env = reloader_guard.acquire_env().unwrap();
+    let tmpl = env.get_template("users.html").unwrap();
+    let rendered = tmpl
+        .render(context! {
+            users => users,
+            oidc_identities => oidc_identities,
+            sso_provider => state.oidc.as_ref().map(|oidc| oidc.provider_name.as_str()),
+            current_user => user,
+        })
+        .unwrap();
+    Html(rendered).into_response()
+}
+
+pub async fn create_user_handler(
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<CreateUserRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let username = request.username.trim();
+    if username.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Username is required").into_response();
+    }
+    if request.password.len() < MIN_PASSWORD_LENGTH {
+        return (StatusCode::BAD_REQUEST, "Password must be at least 8 characters").into_response();
+    }
+
+    let password_hash = match hash_password(&request.password) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password").into_response()
+        }
+    };
+
+    match db::create_user(&state.pool, username, &password_hash, request.role).await {
+        Ok(id) => Json(json!({
+            "status": "success",
+            "id": id,
+        }))
+        .into_response(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            (StatusCode::CONFLICT, "That username is already taken").into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Changes a user's role and/or resets their password.
+pub async fn update_user_handler(
+    Path(user_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<UpdateUserRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let target = match db::get_user(&state.pool, user_id).await {
+        Ok(Some(target)) => target,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    // Validate everything before changing anything
+    let password_hash = match request.password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) if password.len() < MIN_PASSWORD_LENGTH => {
+            return (StatusCode::BAD_REQUEST, "Password must be at least 8 characters")
+                .into_response();
+        }
+        Some(password) => match hash_password(password) {
+            Ok(hash) => Some(hash),
+            Err(_) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password")
+                    .into_response()
+            }
+        },
+        None => None,
+    };
+    if request.role.is_some_and(|role| role != Role::Owner) && target.role == Role::Owner {
+        if let Err(resp) = ensure_other_owner(&state).await {
+            return resp;
+        }
+    }
+
+    if let Some(role) = request.role {
+        if let Err(e) = db::update_user_role(&state.pool, user_id, role).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+    if let Some(password_hash) = password_hash {
+        if let Err(e) = db::update_user_password(&state.pool, user_id, &password_hash).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+        // Anyone still logged in with the old password has to sign in again
+        if let Err(e) = db::revoke_user_sessions(&state.pool, user_id).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+
+    Json(json!({"status": "success"})).into_response()
+}
+
+pub async fn delete_user_handler(
+    Path(user_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+    if user_id == user.id {
+        return (StatusCode::BAD_REQUEST, "You can't delete your own account").into_response();
+    }
+
+    let target = match db::get_user(&state.pool, user_id).await {
+        Ok(Some(target)) => target,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if target.role == Role::Owner {
+        if let Err(resp) = ensure_other_owner(&state).await {
+            return resp;
+        }
+    }
+
+    match db::delete_user(&state.pool, user_id).await {
+        Ok(()) => Json(json!({
+            "status": "success",
+            "message": format!("User {} deleted", target.username),
+        }))
+        .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Lets an identity provider account sign in as a user with single sign-on.
+pub async fn add_oidc_identity_handler(
+    Path(user_id): Path<i64>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+    Json(request): Json<AddOidcIdentityRequest>,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    let value = match request.kind {
+        OidcIdentityKind::Subject => request.value.trim().to_string(),
+        OidcIdentityKind::Email => request.value.trim().to_lowercase(),
+    };
+    if value.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Enter an email address or subject").into_response();
+    }
+    match db::get_user(&state.pool, user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+
+    match db::add_oidc_identity(&state.pool, user_id, request.kind, &value).await {
+        Ok(id) => Json(json!({
+            "status": "success",
+            "id": id,
+        }))
+        .into_response(),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            (StatusCode::CONFLICT, "That account already signs in as a user").into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub async fn delete_oidc_identity_handler(
+    Path((user_id, identity_id)): Path<(i64, i64)>,
+    State(state): State<Arc<AppState>>,
+    user: CurrentUser,
+) -> Response {
+    if let Err(forbidden) = user.require(Role::Owner) {
+        return forbidden.into_response();
+    }
+
+    match db::delete_oidc_identity(&state.pool, identity_id, user_id).await {
+        Ok(true) => Json(json!({"status": "success"})).into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Single sign-on account not found").into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Refuses to remove an owner when they are the only one left.
+async fn ensure_other_owner(state: &AppState) -> Result<(), Response> {
+    match db::count_owners(&state.pool).await {
+        Ok(count) if count > 1 => Ok(()),
+        Ok(_) => {
+            Err((StatusCode::BAD_REQUEST, "There must be at least one owner").into_response())
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()),
+    }
+}
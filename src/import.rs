@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use sqlx::SqlitePool;
+use tokio::fs;
+use tower_cookies::cookie::time::OffsetDateTime;
+
+use crate::config::ImageConfig;
+use crate::db::{self, create_album, find_album_by_name, update_album_metadata};
+use crate::types::{CreateAlbumRequest, Visibility};
+use crate::utils::{compute_checksum, create_album_directory, extract_exif_metadata, process_and_save_images};
+
+/// Number of files read into memory and processed at once.
+pub const IMPORT_BATCH_SIZE: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub albums_created: usize,
+    pub albums_reused: usize,
+    pub images_imported: usize,
+    pub images_skipped: usize,
+    pub images_failed: usize,
+    /// The files counted in `images_failed`.
+    pub failed_files: Vec<PathBuf>,
+}
+
+/// What became of a batch passed to `import_batch`, by the source each image came from.
+pub struct BatchOutcome<T> {
+    /// Stored images, with their checksums
+    pub imported: Vec<(T, String)>,
+    /// Images whose contents were already stored
+    pub skipped: Vec<T>,
+    pub failed: Vec<T>,
+}
+
+/// Imports every subdirectory of `root` as an album.
+///
+/// Albums are matched by name, so re-running an import adds new files to the
+/// existing album and skips files whose checksum is already stored.
+pub async fn import_directory(
+    pool: &SqlitePool,
+    image_config: &ImageConfig,
+    root: &Path,
+) -> Result<ImportSummary, Box<dyn Error + Send + Sync>> {
+    let mut summary = ImportSummary::default();
+
+    let mut album_dirs = Vec::new();
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            album_dirs.push(entry.path());
+        }
+    }
+    album_dirs.sort();
+
+    for dir in album_dirs {
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let files = list_image_files(&dir).await?;
+        if files.is_empty() {
+            println!("Skipping '{}': no JPEG files", name);
+            continue;
+        }
+
+        let (album_id, created) = find_or_create_album(pool, &name, &files).await?;
+        if created {
+            summary.albums_created += 1;
+        } else {
+            summary.albums_reused += 1;
+        }
+
+        let before = summary.images_imported;
+        import_files(pool, image_config, album_id, &files, &mut summary).await?;
+        println!(
+            "{} '{}' (id {}): {} image(s) imported",
+            if created { "Created" } else { "Updated" },
+            name,
+            album_id,
+            summary.images_imported - before
+        );
+    }
+
+    Ok(summary)
+}
+
+/// Returns the album with the given name, creating it if it doesn't exist yet.
+///
+/// New albums are dated from a `YYYY-MM-DD` prefix in the name, falling back to
+/// the earliest EXIF date among `files` and then to today's date.
+pub async fn find_or_create_album(
+    pool: &SqlitePool,
+    name: &str,
+    files: &[PathBuf],
+) -> Result<(i64, bool), Box<dyn Error + Send + Sync>> {
+    if let Some(album_id) = find_album_by_name(pool, name).await? {
+        create_album_directory(album_id).await?;
+        return Ok((album_id, false));
+    }
+
+    let date = match album_date_from_name(name) {
+        Some(date) => date,
+        None => match earliest_exif_date(files).await {
+            Some(date) => date,
+            None => OffsetDateTime::now_utc().date().to_string(),
+        },
+    };
+
+    let album = CreateAlbumRequest {
+        name: name.to_string(),
+        description: None,
+        date,
+        allow_download: false,
+        visibility: Visibility::Public,
+        password: None,
+    };
+    let album_id = create_album(pool, &album, None).await?;
+    create_album_directory(album_id).await?;
+
+    Ok((album_id, true))
+}
+
+/// Runs `files` through the normal processing pipeline in batches, skipping
+/// any whose contents are already stored in the album. Files that can't be read
+/// or processed are added to `summary.failed_files`.
+pub async fn import_files(
+    pool: &SqlitePool,
+    image_config: &ImageConfig,
+    album_id: i64,
+    files: &[PathBuf],
+    summary: &mut ImportSummary,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut known: HashSet<String> = db::get_album_checksums(pool, album_id)
+        .await?
+        .into_iter()
+        .collect();
+
+    for chunk in files.chunks(IMPORT_BATCH_SIZE) {
+        let mut batch = Vec::new();
+        for path in chunk {
+            let data = match fs::read(path).await {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                    summary.images_failed += 1;
+                    summary.failed_files.push(path.clone());
+                    continue;
+                }
+            };
+            let filename = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| "unknown.jpg".to_string());
+            batch.push((path, filename, data));
+        }
+
+        let outcome = import_batch(pool, image_config, album_id, &mut known, batch).await?;
+        summary.images_imported += outcome.imported.len();
+        summary.images_skipped += outcome.skipped.len();
+        summary.images_failed += outcome.failed.len();
+        summary
+            .failed_files
+            .extend(outcome.failed.into_iter().cloned());
+    }
+
+    update_album_metadata(pool, album_id).await?;
+    Ok(())
+}
+
+/// Runs one batch of `(source, filename, contents)` through the normal processing
+/// pipeline, skipping images whose checksum is in `known` and adding the others.
+///
+/// Images that fail are taken out of `known` again, so a copy in a later batch is
+/// retried. Copies within the batch fail along with them.
+pub async fn import_batch<T>(
+    pool: &SqlitePool,
+    image_config: &ImageConfig,
+    album_id: i64,
+    known: &mut HashSet<String>,
+    files: Vec<(T, String, Vec<u8>)>,
+) -> Result<BatchOutcome<T>, Box<dyn Error + Send + Sync>> {
+    let mut outcome = BatchOutcome {
+        imported: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    let mut batch = Vec::new();
+    let mut sources = Vec::new();
+    let mut copies = Vec::new();
+    for (source, filename, data) in files {
+        let checksum = compute_checksum(&data);
+        if sources.iter().any(|(_, queued)| *queued == checksum) {
+            copies.push((source, checksum));
+        } else if !known.insert(checksum.clone()) {
+            outcome.skipped.push(source);
+        } else {
+            batch.push((filename, data));
+            sources.push((source, checksum));
+        }
+    }
+    if batch.is_empty() {
+        return Ok(outcome);
+    }
+
+    let processed = process_and_save_images(pool, image_config, album_id, batch).await?;
+    if processed == sources.len() {
+        outcome.imported = sources;
+        outcome.skipped.extend(copies.into_iter().map(|(source, _)| source));
+        return Ok(outcome);
+    }
+
+    // Processing doesn't say which images failed, so look for the ones that
+    // weren't stored
+    let stored: HashSet<String> = db::get_album_checksums(pool, album_id)
+        .await?
+        .into_iter()
+        .collect();
+    for (source, checksum) in sources {
+        if stored.contains(&checksum) {
+            outcome.imported.push((source, checksum));
+        } else {
+            known.remove(&checksum);
+            outcome.failed.push(source);
+        }
+    }
+    for (source, checksum) in copies {
+        if stored.contains(&checksum) {
+            outcome.skipped.push(source);
+        } else {
+            outcome.failed.push(source);
+        }
+    }
+    Ok(outcome)
+}
+
+/// Lists the JPEG files directly inside `dir`, sorted by name.
+pub async fn list_image_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if entry.file_type().await?.is_file() && is_supported_image(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Only JPEGs can be decoded by the processing pipeline.
+pub fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+        .unwrap_or(false)
+}
+
+/// Parses a leading `YYYY-MM-DD` (or `YYYY_MM_DD` / `YYYY.MM.DD`) from a folder name.
+fn album_date_from_name(name: &str) -> Option<String> {
+    let prefix = name.get(..10)?;
+    let parts: Vec<&str> = prefix.split(['-', '_', '.']).collect();
+    match parts.as_slice() {
+        [year, month, day]
+            if year.len() == 4
+                && month.len() == 2
+                && day.len() == 2
+                && prefix.chars().filter(char::is_ascii_digit).count() == 8 =>
+        {
+            Some(format!("{}-{}-{}", year, month, day))
+        }
+        _ => None,
+    }
+}
+
+/// Converts an EXIF `YYYY:MM:DD HH:MM:SS` timestamp into an album date.
+fn exif_date_to_album_date(value: &str) -> Option<String> {
+    album_date_from_name(&value.get(..10)?.replace(':', "-"))
+}
+
+async fn earliest_exif_date(files: &[PathBuf]) -> Option<String> {
+    let mut earliest: Option<String> = None;
+    for path in files {
+        let Ok(data) = fs::read(path).await else {
+            continue;
+        };
+        let Some(date) = extract_exif_metadata(&data).and_then(|m| exif_date_to_album_date(&m.8))
+        else {
+            continue;
+        };
+        if earliest.as_ref().is_none_or(|e| date < *e) {
+            earliest = Some(date);
+        }
+    }
+    earliest
+}
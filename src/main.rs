@@ -1,27 +1,44 @@
+use auth::api_tokens::api_token_auth;
+use auth::csrf::{csrf_protection, token_api_csrf_protection};
+use axum::middleware;
 use axum::routing::{delete, get, post, put};
 use axum::Router;
+use config::Config;
 use dotenv::dotenv;
 use handlers::admin::admin_handler;
-use handlers::album::album_handler;
+use handlers::album::{
+    album_download_handler, album_handler, album_unlock_handler, selected_download_handler,
+};
 use handlers::home::home_handler;
-use handlers::login::{login_handler, login_post_handler, logout_handler};
+use handlers::login::{
+    login_handler, login_post_handler, login_two_factor_handler, logout_handler,
+};
 use std::env;
 use std::net::SocketAddr;
+use std::process;
 use std::sync::Arc;
 use tower_cookies::CookieManagerLayer;
 use tower_governor::governor::GovernorConfigBuilder;
 use tower_governor::GovernorLayer;
 use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::services::ServeDir;
-use tower_http::compression::{CompressionLayer, CompressionLevel};
+use tower_http::compression::predicate::{NotForContentType, Predicate};
+use tower_http::compression::{CompressionLayer, CompressionLevel, DefaultPredicate};
 
 
+mod archive;
 mod auth;
+mod cli;
+mod config;
 mod db;
 mod handlers;
+mod import;
 mod state;
+mod static_site;
 mod types;
 mod utils;
+mod watcher;
+mod xmp;
 
 #[tokio::main]
 async fn main() {
@@ -30,17 +47,49 @@ async fn main() {
 
     dotenv().ok();
 
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration:\n{}", e);
+            process::exit(1);
+        }
+    };
+
+    // Run a CLI subcommand instead of the server if one was given
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        cli::run(&config, state::connect_db(&config).await, &args).await;
+        return;
+    }
+
     // Initialize the database pool
-    let pool = state::init_db().await;
+    let pool = state::init_db(&config).await;
+
+    // Watch the drop folder for new images if one is configured
+    if let Some(inbox) = &config.inbox.dir {
+        watcher::spawn(
+            pool.clone(),
+            config.images,
+            inbox.clone(),
+            config.inbox.archive_dir.clone(),
+        );
+    }
+
+    let limits = config.limits.clone();
+    let addr = format!("{}:{}", config.server.host, config.server.port);
+
+    let settings = db::get_site_settings(&pool)
+        .await
+        .expect("Failed to load site settings");
 
     // Initialize the application state
-    let state = state::init_state(pool);
+    let state = state::init_state(config, pool, settings);
 
     // Configure rate limiting
     let governor_conf = Arc::new(
         GovernorConfigBuilder::default()
-            .per_second(5) // Allow 2 requests per second
-            .burst_size(10) // Allow a burst of up to 5 requests
+            .per_second(limits.requests_per_second)
+            .burst_size(limits.request_burst)
             .use_headers() // Enable `x-ratelimit-*` headers
             .finish()
             .unwrap(),
@@ -48,14 +97,16 @@ async fn main() {
 
     // Create routers for static files and uploads
     let static_router = Router::new().nest_service("/static", ServeDir::new("static"));
-    let uploads_router = Router::new().nest_service("/uploads", ServeDir::new("uploads"));
+    // Uploads of non-public albums need a signed URL, checked before serving
+    let uploads_router = Router::new()
+        .route(
+            "/uploads/{album_id}/{quality}/{filename}",
+            get(handlers::uploads::uploads_handler),
+        )
+        .with_state(state.clone());
 
-    // Create the main app router
-    let app = Router::new()
-        .route("/", get(home_handler))
-        .route("/login", get(login_handler).post(login_post_handler))
-        .route("/admin", get(admin_handler))
-        .route("/albums/{id}", get(album_handler))
+    // Album and image API routes also accept personal API tokens
+    let token_api_router = Router::new()
         .route("/api/albums", post(handlers::admin::create_album_handler))
         .route(
             "/api/albums/{id}",
@@ -66,34 +117,181 @@ async fn main() {
             put(handlers::admin::update_album_handler),
         )
         .route("/api/albums/{id}", get(handlers::admin::get_album_handler))
+        .route(
+            "/api/albums/{id}/share-links",
+            get(handlers::share::list_share_links_handler)
+                .post(handlers::share::create_share_link_handler),
+        )
+        .route(
+            "/api/albums/{id}/permissions",
+            get(handlers::permissions::list_album_permissions_handler),
+        )
+        .route(
+            "/api/albums/{id}/permissions/{user_id}",
+            put(handlers::permissions::grant_album_permission_handler)
+                .delete(handlers::permissions::revoke_album_permission_handler),
+        )
+        .route(
+            "/api/albums/{id}/tags",
+            put(handlers::tags::update_album_tags_handler),
+        )
+        .route(
+            "/api/albums/{id}/image-tags",
+            post(handlers::tags::update_image_tags_handler),
+        )
+        .route(
+            "/api/albums/{id}/image-ratings",
+            post(handlers::admin::rate_images_handler),
+        )
+        .route(
+            "/api/smart-albums",
+            get(handlers::smart_albums::list_smart_albums_handler)
+                .post(handlers::smart_albums::create_smart_album_handler),
+        )
+        .route(
+            "/api/smart-albums/{id}",
+            put(handlers::smart_albums::update_smart_album_handler)
+                .delete(handlers::smart_albums::delete_smart_album_handler),
+        )
+        .route(
+            "/api/share-links/{id}/revoke",
+            post(handlers::share::revoke_share_link_handler),
+        )
         .route(
             "/api/images/{id}",
             delete(handlers::admin::delete_image_handler),
         )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api_token_auth,
+        ))
+        .layer(middleware::from_fn(token_api_csrf_protection));
+
+    // Create the main app router
+    let app = Router::new()
+        .route("/", get(home_handler))
+        .route("/login", get(login_handler).post(login_post_handler))
+        .route("/admin", get(admin_handler))
+        .route("/login/2fa", post(login_two_factor_handler))
+        .route("/login/oidc", get(handlers::oidc::oidc_login_handler))
+        .route(
+            "/login/oidc/callback",
+            get(handlers::oidc::oidc_callback_handler),
+        )
+        .route("/admin/users", get(handlers::users::users_page_handler))
+        .route(
+            "/admin/logins",
+            get(handlers::login_events::login_events_page_handler),
+        )
+        .route(
+            "/admin/settings",
+            get(handlers::settings::settings_page_handler),
+        )
+        .route(
+            "/admin/security",
+            get(handlers::two_factor::security_page_handler),
+        )
+        .route("/albums/{id}", get(album_handler))
+        .route("/search", get(handlers::search::search_page_handler))
+        .route("/api/search", get(handlers::search::search_api_handler))
+        .route("/browse", get(handlers::browse::browse_page_handler))
+        .route("/tags/{slug}", get(handlers::tags::tag_page_handler))
+        .route(
+            "/smart-albums/{id}",
+            get(handlers::smart_albums::smart_album_handler),
+        )
+        .route(
+            "/api/images/search",
+            get(handlers::browse::image_search_handler),
+        )
+        .route("/albums/{id}/unlock", post(album_unlock_handler))
+        .route("/s/{token}", get(handlers::share::share_link_handler))
+        .route(
+            "/albums/{id}/download",
+            get(album_download_handler).post(selected_download_handler),
+        )
+        .route(
+            "/api/2fa/setup",
+            post(handlers::two_factor::setup_two_factor_handler),
+        )
+        .route(
+            "/api/2fa/enable",
+            post(handlers::two_factor::enable_two_factor_handler),
+        )
+        .route(
+            "/api/2fa/recovery-codes",
+            post(handlers::two_factor::regenerate_recovery_codes_handler),
+        )
+        .route(
+            "/api/2fa/disable",
+            post(handlers::two_factor::disable_two_factor_handler),
+        )
+        .route(
+            "/admin/tokens",
+            get(handlers::api_tokens::api_tokens_page_handler),
+        )
+        .route(
+            "/api/tokens",
+            post(handlers::api_tokens::create_api_token_handler),
+        )
+        .route(
+            "/api/tokens/{id}",
+            delete(handlers::api_tokens::delete_api_token_handler),
+        )
+        .route(
+            "/api/sessions/revoke-all",
+            post(handlers::sessions::revoke_all_sessions_handler),
+        )
+        .route(
+            "/api/sessions/{id}",
+            delete(handlers::sessions::revoke_session_handler),
+        )
+        .route(
+            "/api/settings",
+            put(handlers::settings::update_settings_handler),
+        )
+        .route("/api/users", post(handlers::users::create_user_handler))
+        .route(
+            "/api/users/{id}",
+            put(handlers::users::update_user_handler)
+                .delete(handlers::users::delete_user_handler),
+        )
+        .route(
+            "/api/users/{id}/oidc-identities",
+            post(handlers::users::add_oidc_identity_handler),
+        )
+        .route(
+            "/api/users/{id}/oidc-identities/{identity_id}",
+            delete(handlers::users::delete_oidc_identity_handler),
+        )
         .route("/logout", get(logout_handler))
+        .layer(middleware::from_fn(csrf_protection))
+        .merge(token_api_router)
         .layer(
             CompressionLayer::new()
                 .gzip(true)
                 .br(true)
                 .deflate(true)
                 .quality(CompressionLevel::Default)
+                // ZIP downloads are already-compressed JPEGs streamed as they're written
+                .compress_when(
+                    DefaultPredicate::new().and(NotForContentType::const_new("application/zip")),
+                ),
         )
         .layer(CookieManagerLayer::new())
         .layer(GovernorLayer {
             config: governor_conf,
         })
-        .layer(RequestBodyLimitLayer::new(2000 * 1024 * 1024)) // Limit request body size to 2 GB
-        .layer(axum::extract::DefaultBodyLimit::max(1024 * 1024 * 1024)) // 1GB limit
+        .layer(RequestBodyLimitLayer::new(limits.max_request_mb * 1024 * 1024))
+        .layer(axum::extract::DefaultBodyLimit::max(limits.max_upload_mb * 1024 * 1024))
         .with_state(state)
         .merge(static_router)
         .merge(uploads_router);
 
-    // Read host and port from environment variables
-    let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("{}:{}", host, port).parse::<SocketAddr>().unwrap();
-
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap_or_else(|e| {
+        eprintln!("Failed to listen on {}: {}", addr, e);
+        process::exit(1);
+    });
     let addr = listener.local_addr().unwrap();
     println!("listening on http://{}", addr);
     axum::serve(
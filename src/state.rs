@@ -1,73 +1,200 @@
-use crate::types::AppState;
+use crate::auth::signed_urls::derive_signing_key;
+use crate::config::Config;
+use crate::db;
+use crate::types::{AppState, OidcConfig, Role, SiteSettings};
+use argon2::PasswordHash;
+use minijinja::value::{Object, Value};
 use minijinja::{path_loader, Environment};
 use minijinja_autoreload::AutoReloader;
+use sqlx::migrate::Migrator;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::SqlitePool;
-use std::env;
+use rust_embed::RustEmbed;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex as AsyncMutex;
 
 pub const TEMPLATES_DIR: &str = "templates";
 
+/// The migrations in `migrations/`, embedded in the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// The default templates in `templates/`, embedded in release builds. Debug builds
+/// read them from the source tree instead, so edits show up without recompiling.
+#[derive(RustEmbed)]
+#[folder = "templates/"]
+struct DefaultTemplates;
+
+/// Returns the source tree's template directory, watched for changes in debug builds.
+pub fn template_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(TEMPLATES_DIR)
+}
+
+/// Builds the template environment shared by the server and the static site export.
+pub fn create_environment(
+    config: &Config,
+    settings: &Arc<RwLock<SiteSettings>>,
+) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_loader(template_loader(config.theme.path()));
+
+    // Add a custom context processor to inject `app_env`
+    env.add_global("app_env", config.server.app_env.clone());
+    env.add_global("site", Value::from_object(SiteGlobal(Arc::clone(settings))));
+    env
+}
+
+/// Loads a template from the theme directory if it has its own copy, otherwise the
+/// default one.
+fn template_loader(
+    theme_path: Option<PathBuf>,
+) -> impl Fn(&str) -> Result<Option<String>, minijinja::Error> + Send + Sync + 'static {
+    let theme_loader = theme_path.map(path_loader);
+    move |name| {
+        if let Some(theme_loader) = &theme_loader {
+            if let Some(source) = theme_loader(name)? {
+                return Ok(Some(source));
+            }
+        }
+        DefaultTemplates::get(name)
+            .map(|file| {
+                String::from_utf8(file.data.into_owned()).map_err(|e| {
+                    minijinja::Error::new(
+                        minijinja::ErrorKind::InvalidOperation,
+                        "template is not valid UTF-8",
+                    )
+                    .with_source(e)
+                })
+            })
+            .transpose()
+    }
+}
+
+/// The `site` template global. It reads the settings on every lookup, so changes
+/// made in the admin panel show up without rebuilding the environment. Besides the
+/// `SiteSettings` fields it has `name`, the title with the owner's name.
+#[derive(Debug)]
+struct SiteGlobal(Arc<RwLock<SiteSettings>>);
+
+impl Object for SiteGlobal {
+    fn get_value(self: &Arc<Self>, key: &Value) -> Option<Value> {
+        let settings = self.0.read().unwrap();
+        match key.as_str()? {
+            "name" => Some(Value::from(settings.display_name())),
+            key => Value::from_serialize(&*settings).get_attr(key).ok(),
+        }
+    }
+}
+
 /// Initializes the application state with the reloader and other configurations.
-pub fn init_state(pool: SqlitePool) -> Arc<AppState> {
+pub fn init_state(config: Config, pool: SqlitePool, settings: SiteSettings) -> Arc<AppState> {
+    let settings = Arc::new(RwLock::new(settings));
     let reloader = if cfg!(debug_assertions) {
-        let auto_reload_mode = env::var("AUTO_RELOAD_MODE").unwrap_or_else(|_| "0".to_string());
+        let config = config.clone();
+        let settings = Arc::clone(&settings);
         Arc::new(AsyncMutex::new(AutoReloader::new(move |notifier| {
-            let template_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(TEMPLATES_DIR);
-            let mut env = Environment::new();
-            env.set_loader(path_loader(&template_path));
-
-            // Add a custom context processor to inject `app_env`
-            env.add_global(
-                "app_env",
-                env::var("APP_ENV").unwrap_or_else(|_| "production".to_string()),
-            );
-
-            match auto_reload_mode.as_str() {
-                "1" => notifier.set_fast_reload(true),
-                "2" => notifier.watch_path(&template_path, true),
+            let env = create_environment(&config, &settings);
+
+            match config.server.auto_reload_mode {
+                1 => notifier.set_fast_reload(true),
+                2 => {
+                    notifier.watch_path(template_path(), true);
+                    if let Some(theme_path) = config.theme.path() {
+                        notifier.watch_path(&theme_path, true);
+                    }
+                }
                 _ => {}
             }
 
             Ok(env)
         })))
     } else {
+        let config = config.clone();
+        let settings = Arc::clone(&settings);
         Arc::new(AsyncMutex::new(AutoReloader::new(move |_| {
-            let template_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(TEMPLATES_DIR);
-            let mut env = Environment::new();
-            env.set_loader(path_loader(&template_path));
-
-            // Add a custom context processor to inject `app_env`
-            env.add_global(
-                "app_env",
-                env::var("APP_ENV").unwrap_or_else(|_| "production".to_string()),
-            );
-            Ok(env)
+            Ok(create_environment(&config, &settings))
         })))
     };
 
+    let jwt_secret = config.auth.jwt_secret.clone();
     Arc::new(AppState {
         reloader: Arc::clone(&reloader),
-        jwt_secret: env::var("JWT_SECRET").expect("JWT_SECRET must be set"),
+        settings,
+        url_signing_key: derive_signing_key(&jwt_secret),
+        jwt_secret,
         pool,
+        oidc: oidc_config(&config),
+        config,
     })
 }
 
-pub async fn init_db() -> SqlitePool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to the database");
+/// Builds the single sign-on client if an issuer is configured. `Config::load` has
+/// already checked the settings it needs are there.
+fn oidc_config(config: &Config) -> Option<OidcConfig> {
+    let settings = &config.oidc;
+    Some(OidcConfig {
+        issuer: settings.issuer.clone()?,
+        client_id: settings.client_id.clone()?,
+        client_secret: settings.client_secret.clone(),
+        redirect_url: settings.redirect_url.clone()?,
+        provider_name: settings.provider_name.clone(),
+        http: reqwest::Client::new(),
+    })
+}
+
+/// Connects to the database, brings it up to date and creates the first owner.
+pub async fn init_db(config: &Config) -> SqlitePool {
+    let pool = connect_db(config).await;
 
     // Run migrations
-    sqlx::migrate!()
-        .run(&pool)
+    run_migrations(&pool)
         .await
         .expect("Failed to run migrations");
 
+    seed_owner(config, &pool).await;
+
     pool
 }
+
+/// Connects to the configured database without changing anything.
+pub async fn connect_db(config: &Config) -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .connect(&config.database.url)
+        .await
+        .expect("Failed to connect to the database")
+}
+
+/// Applies any migrations the database doesn't have yet.
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::migrate::MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Creates the first owner account from `ADMIN_USERNAME` and `ADMIN_PASSWORD` (an
+/// argon2 hash) when there are no users yet. Once any user exists these are ignored.
+async fn seed_owner(config: &Config, pool: &SqlitePool) {
+    if db::count_users(pool).await.expect("Failed to count users") > 0 {
+        return;
+    }
+
+    let (Some(username), Some(password_hash)) =
+        (&config.auth.admin_username, &config.auth.admin_password)
+    else {
+        eprintln!(
+            "No users exist yet; set ADMIN_USERNAME and ADMIN_PASSWORD (auth.admin_username and \
+             auth.admin_password) to create the first owner"
+        );
+        return;
+    };
+    if PasswordHash::new(password_hash).is_err() {
+        eprintln!(
+            "ADMIN_PASSWORD is not an argon2 hash; use `photo-gallery hash-password` to create one"
+        );
+        return;
+    }
+
+    db::create_user(pool, username, password_hash, Role::Owner)
+        .await
+        .expect("Failed to create owner account");
+    println!("Created owner account '{}'", username);
+}
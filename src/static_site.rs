@@ -0,0 +1,120 @@
+use minijinja::context;
+use sqlx::SqlitePool;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::fs;
+
+use crate::archive::image_path;
+use crate::config::Config;
+use crate::db::{get_album_with_images, get_albums_with_oldest_image, get_site_settings};
+use crate::handlers::album::get_adjacent_albums;
+use crate::state::create_environment;
+use crate::utils::ImageQuality;
+
+/// Top-level paths the templates link to absolutely, rewritten to be relative.
+const LINKED_DIRS: [&str; 3] = ["albums/", "uploads/", "static/"];
+
+#[derive(Debug, Default)]
+pub struct StaticExportSummary {
+    pub pages: usize,
+    pub images: usize,
+}
+
+/// Renders the home page and every album page into `out`, together with the
+/// thumbnails, optimized images and static assets they reference, so the
+/// directory can be served by any static file host.
+///
+/// Only public albums are exported. Album pages are written to
+/// `albums/<id>/index.html` so `/albums/<id>` links keep working once made relative.
+pub async fn export_static_site(
+    pool: &SqlitePool,
+    config: &Config,
+    out: &Path,
+) -> Result<StaticExportSummary, Box<dyn Error + Send + Sync>> {
+    let settings = Arc::new(RwLock::new(get_site_settings(pool).await?));
+    let mut env = create_environment(config, &settings);
+    // Lets templates hide links to server-only features like downloads and login
+    env.add_global("static_export", true);
+
+    let mut summary = StaticExportSummary::default();
+    fs::create_dir_all(out).await?;
+
+    let albums = get_albums_with_oldest_image(pool, false).await?;
+    let home = env.get_template("home.html")?.render(context! {
+        albums => albums,
+    })?;
+    fs::write(out.join("index.html"), relativize(&home, "")).await?;
+    summary.pages += 1;
+
+    for (album, _, _) in &albums {
+        let (album, images) = get_album_with_images(pool, album.id).await?;
+        let (prev_album, next_album) = get_adjacent_albums(pool, album.id).await;
+
+        let page = env.get_template("album.html")?.render(context! {
+            album => album,
+            images => images,
+            // Only public albums are exported, so image URLs are never signed
+            image_query => "",
+            prev_album => prev_album,
+            next_album => next_album,
+        })?;
+        let album_dir = out.join("albums").join(album.id.to_string());
+        fs::create_dir_all(&album_dir).await?;
+        fs::write(album_dir.join("index.html"), relativize(&page, "../../")).await?;
+        summary.pages += 1;
+
+        for quality in [ImageQuality::Thumbnail, ImageQuality::Optimized] {
+            let dir = out
+                .join("uploads")
+                .join(album.id.to_string())
+                .join(quality.as_str());
+            fs::create_dir_all(&dir).await?;
+            for image in &images {
+                fs::copy(
+                    image_path(album.id, quality, &image.filename),
+                    dir.join(&image.filename),
+                )
+                .await?;
+            }
+        }
+        summary.images += images.len();
+    }
+
+    copy_dir(Path::new("static"), &out.join("static")).await?;
+
+    Ok(summary)
+}
+
+/// Rewrites root-relative links (`/albums/..`, `/uploads/..`, `/static/..` and
+/// the home page) so they resolve from a page `root` levels below the site root.
+fn relativize(html: &str, root: &str) -> String {
+    let mut html = html.replace("href=\"/\"", &format!("href=\"{}index.html\"", root));
+    for dir in LINKED_DIRS {
+        for prefix in ['"', '\'', '('] {
+            html = html.replace(
+                &format!("{}/{}", prefix, dir),
+                &format!("{}{}{}", prefix, root, dir),
+            );
+        }
+    }
+    html
+}
+
+/// Recursively copies a directory tree.
+async fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut stack: Vec<(PathBuf, PathBuf)> = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((src, dst)) = stack.pop() {
+        fs::create_dir_all(&dst).await?;
+        let mut entries = fs::read_dir(&src).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = dst.join(entry.file_name());
+            if entry.file_type().await?.is_dir() {
+                stack.push((entry.path(), target));
+            } else {
+                fs::copy(entry.path(), target).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,19 +1,391 @@
+use crate::config::Config;
 use minijinja_autoreload::AutoReloader;
 use serde::{Deserialize, Serialize};
 use sqlx::{prelude::FromRow, SqlitePool};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex as AsyncMutex;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    /// Id of the logged-in user
     pub sub: String,
+    /// Id of the session the token belongs to, so it stops working when the session is revoked
+    pub jti: String,
     pub exp: usize,
 }
 
+/// What a user may do across the whole site. Each role can do everything the roles
+/// below it can; what they can do with a particular album is an `AlbumPermission`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Role {
+    /// Can see the dashboard and albums shared with them
+    Viewer,
+    /// Can also create albums of their own
+    Editor,
+    /// Can manage every album and all users
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        match value {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "owner" => Some(Role::Owner),
+            _ => None,
+        }
+    }
+}
+
+/// What a user may do with one album. Each level includes the ones before it.
+///
+/// `View`, `Upload` and `Edit` are granted per user in `album_permissions`; `Owner`
+/// belongs to the album's creator and to site owners.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AlbumPermission {
+    /// See the album even if it isn't public
+    View,
+    /// Add images
+    Upload,
+    /// Change details and visibility, delete images and manage share links
+    Edit,
+    /// Delete the album and grant permissions to other users
+    Owner,
+}
+
+impl AlbumPermission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlbumPermission::View => "view",
+            AlbumPermission::Upload => "upload",
+            AlbumPermission::Edit => "edit",
+            AlbumPermission::Owner => "owner",
+        }
+    }
+}
+
+/// A user's granted permission on an album, as listed to the album's owner.
+#[derive(Debug, Serialize)]
+pub struct AlbumGrant {
+    pub user_id: i64,
+    pub username: String,
+    pub permission: AlbumPermission,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantPermissionRequest {
+    pub permission: AlbumPermission,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    pub created_at: String,
+    /// Disabled users can't sign in or use their API tokens
+    pub disabled: bool,
+}
+
+/// The logged-in user, extracted from the `auth_token` cookie on admin routes, or
+/// from an API token on the routes that accept one.
+#[derive(Debug, Clone, Serialize)]
+pub struct CurrentUser {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    /// What the API token used for the request allows, or `None` for a browser session
+    #[serde(skip)]
+    pub scopes: Option<Vec<ApiScope>>,
+    /// The browser session the request belongs to, or `None` for API tokens
+    #[serde(skip)]
+    pub session_id: Option<i64>,
+}
+
+/// A logged-in browser session, as listed to its user.
+#[derive(Debug, Serialize)]
+pub struct Session {
+    pub id: i64,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub expires_at: String,
+}
+
+/// How a login attempt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    /// Unknown username or wrong password
+    BadPassword,
+    /// Wrong two-factor or recovery code
+    BadCode,
+    /// Refused without checking the password because of earlier failures
+    LockedOut,
+    /// Right password for an account that has been disabled
+    Disabled,
+}
+
+impl LoginOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginOutcome::Success => "success",
+            LoginOutcome::BadPassword => "bad_password",
+            LoginOutcome::BadCode => "bad_code",
+            LoginOutcome::LockedOut => "locked_out",
+            LoginOutcome::Disabled => "disabled",
+        }
+    }
+}
+
+/// A recorded login attempt, as listed in the admin panel.
+#[derive(Debug, Serialize)]
+pub struct LoginEvent {
+    pub id: i64,
+    pub username: String,
+    pub user_id: Option<i64>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: LoginOutcome,
+    pub created_at: String,
+}
+
+/// What an API token may be used for, on top of what its user's role and album
+/// permissions allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiScope {
+    /// Read albums and their image lists
+    #[serde(rename = "albums:read")]
+    AlbumsRead,
+    /// Create, update and delete albums, upload images and manage share links and permissions
+    #[serde(rename = "albums:write")]
+    AlbumsWrite,
+    /// Delete single images
+    #[serde(rename = "images:delete")]
+    ImagesDelete,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::AlbumsRead => "albums:read",
+            ApiScope::AlbumsWrite => "albums:write",
+            ApiScope::ImagesDelete => "images:delete",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ApiScope> {
+        match value {
+            "albums:read" => Some(ApiScope::AlbumsRead),
+            "albums:write" => Some(ApiScope::AlbumsWrite),
+            "images:delete" => Some(ApiScope::ImagesDelete),
+            _ => None,
+        }
+    }
+}
+
+/// A personal API token, without the token itself.
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    /// Space-separated `ApiScope`s
+    pub scopes: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    /// Days until the token stops working, or never if unset
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRequest {
+    pub role: Option<Role>,
+    /// New password, left unchanged if unset or empty
+    pub password: Option<String>,
+}
+
+/// A user's two-factor authentication settings.
+#[derive(Debug)]
+pub struct TwoFactor {
+    /// Base32 TOTP secret, set as soon as enrollment starts
+    pub secret: Option<String>,
+    /// Whether a code has been confirmed and login asks for one
+    pub enabled: bool,
+}
+
+/// Claims of the short-lived cookie issued when a user with two-factor
+/// authentication has entered the right password, or signed in with single
+/// sign-on, but not yet entered a code.
+///
+/// The audience keeps it from ever being accepted as an `auth_token`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TwoFactorChallengeClaims {
+    pub sub: String,
+    pub aud: String,
+    pub exp: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+/// Claims of the short-lived cookie that carries a single sign-on login from the
+/// redirect to the identity provider to its callback.
+///
+/// The audience keeps it from ever being accepted as an `auth_token`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcLoginClaims {
+    /// Must come back unchanged from the identity provider
+    pub state: String,
+    /// Must be in the ID token, tying it to this login
+    pub nonce: String,
+    /// PKCE verifier, sent with the code to prove this server started the login
+    pub code_verifier: String,
+    pub aud: String,
+    pub exp: usize,
+}
+
+/// Single sign-on settings, from the `OIDC_*` environment variables.
+pub struct OidcConfig {
+    /// Issuer URL; its `/.well-known/openid-configuration` describes the provider
+    pub issuer: String,
+    pub client_id: String,
+    /// Not needed for public clients, which rely on PKCE alone
+    pub client_secret: Option<String>,
+    /// This server's `/login/oidc/callback` URL, as registered with the provider
+    pub redirect_url: String,
+    /// Shown on the login button
+    pub provider_name: String,
+    pub http: reqwest::Client,
+}
+
+/// How an identity provider account is matched to a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum OidcIdentityKind {
+    /// The provider's stable `sub` id for the account
+    Subject,
+    /// The account's email address, if the provider has verified it
+    Email,
+}
+
+impl OidcIdentityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OidcIdentityKind::Subject => "subject",
+            OidcIdentityKind::Email => "email",
+        }
+    }
+}
+
+/// An identity provider account allowed to sign in as a user.
+#[derive(Debug, Serialize)]
+pub struct OidcIdentity {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: OidcIdentityKind,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddOidcIdentityRequest {
+    pub kind: OidcIdentityKind,
+    pub value: String,
+}
+
+/// Sent with changes that need the user to re-enter their password.
+#[derive(Debug, Deserialize)]
+pub struct ConfirmPasswordRequest {
+    pub password: String,
+}
+
+/// Claims of the cookie that unlocks a single album, either by its password or
+/// through a share link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlbumAccessClaims {
+    pub album_id: i64,
+    pub exp: usize,
+    /// Set when access came from a share link, which is re-checked on every request
+    #[serde(default)]
+    pub share_link_id: Option<i64>,
+}
+
+/// Site-wide branding, edited from the admin panel and available to every
+/// template as `site`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteSettings {
+    pub site_title: String,
+    pub tagline: Option<String>,
+    pub owner_name: Option<String>,
+    pub contact_links: Vec<ContactLink>,
+    /// Path or URL of the page background
+    pub background_image: Option<String>,
+    /// CSS colour as `#rrggbb`
+    pub accent_color: String,
+}
+
+impl SiteSettings {
+    /// The site's name as shown in page titles, e.g. "Dylan's Photos".
+    pub fn display_name(&self) -> String {
+        match &self.owner_name {
+            Some(owner_name) => format!("{}'s {}", owner_name, self.site_title),
+            None => self.site_title.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactLink {
+    pub label: String,
+    pub url: String,
+}
+
 pub struct AppState {
+    pub config: Config,
     pub reloader: Arc<AsyncMutex<AutoReloader>>,
+    /// Shared with the template environment, so changes show on the next page load
+    pub settings: Arc<RwLock<SiteSettings>>,
     pub jwt_secret: String,
+    /// Key for signing `/uploads` URLs of non-public albums, derived from `jwt_secret`
+    pub url_signing_key: Vec<u8>,
     pub pool: SqlitePool,
+    /// Set if single sign-on is configured
+    pub oidc: Option<OidcConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,9 +393,60 @@ pub struct CreateAlbumRequest {
     pub name: String,
     pub description: Option<String>,
     pub date: String,
+    #[serde(default)]
+    pub allow_download: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Plain-text password for `Visibility::Password` albums, hashed before storing
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Changes to an album's details. Download and visibility settings that are left
+/// out keep their stored values.
+#[derive(Debug, Deserialize)]
+pub struct UpdateAlbumRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub date: String,
+    #[serde(default)]
+    pub allow_download: Option<bool>,
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// New plain-text password for `Visibility::Password` albums; left out or blank
+    /// keeps the current one
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Who can see an album.
+///
+/// Only public albums are listed on the home page; unlisted albums are reachable by
+/// anyone with the link, password albums by anyone who knows the password, and
+/// private albums only by users with permission to view them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+    Password,
 }
 
-#[derive(Debug, Serialize, FromRow)]
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Private => "private",
+            Visibility::Password => "password",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Album {
     pub id: i64,
     pub name: String,
@@ -33,13 +456,19 @@ pub struct Album {
     pub camera_model: Option<String>,
     pub lens_model: Option<String>,
     pub aperture: Option<String>,
+    #[serde(default)]
+    pub allow_download: bool,
+    #[serde(default)]
+    pub visibility: Visibility,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Image {
     pub id: i64,
     pub album_id: i64,
     pub filename: String,
+    pub original_filename: Option<String>,
+    pub checksum: Option<String>,
     pub camera_make: Option<String>,
     pub camera_model: Option<String>,
     pub lens_model: Option<String>,
@@ -50,4 +479,202 @@ pub struct Image {
     pub light_source: Option<String>,
     pub date_created: Option<String>,
     pub file_size: i64,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub caption: Option<String>,
+    /// Comma-separated
+    #[serde(default)]
+    pub keywords: Option<String>,
+    /// 1 to 5 stars, or 0 if not rated
+    #[serde(default)]
+    pub rating: i64,
+}
+
+/// Title, caption and keywords read from an image file's EXIF or XMP metadata.
+#[derive(Debug, Default)]
+pub struct ImageDescription {
+    pub title: Option<String>,
+    pub caption: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+/// An album matching a search. Matched words in `name` and `description` are
+/// wrapped in the control characters `\u{1}` and `\u{2}`.
+#[derive(Debug, Serialize)]
+pub struct AlbumSearchHit {
+    pub id: i64,
+    pub name: String,
+    /// The part of the description around the matches
+    pub description: Option<String>,
+    pub date: String,
+    pub num_images: i64,
+    pub cover_image: Option<String>,
+}
+
+/// An image matching a search, with matches marked like in `AlbumSearchHit`.
+#[derive(Debug, Serialize)]
+pub struct ImageSearchHit {
+    pub id: i64,
+    pub album_id: i64,
+    pub album_name: String,
+    pub filename: String,
+    pub title: Option<String>,
+    pub caption: Option<String>,
+    pub keywords: Option<String>,
+    pub equipment: Option<String>,
+}
+
+/// A label for images and albums, shown at `/tags/<slug>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub slug: String,
+}
+
+/// An image with its tags, as shown on album and tag pages.
+#[derive(Debug, Serialize)]
+pub struct TaggedImage {
+    #[serde(flatten)]
+    pub image: Image,
+    pub tags: Vec<Tag>,
+}
+
+/// A public album with a tag, as listed on the tag's page.
+#[derive(Debug, Serialize)]
+pub struct TaggedAlbum {
+    pub id: i64,
+    pub name: String,
+    pub date: String,
+    pub num_images: i64,
+    pub cover_image: Option<String>,
+}
+
+/// Replaces an album's own tags.
+#[derive(Debug, Deserialize)]
+pub struct AlbumTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// Adds tags to and removes tags from several images of an album at once.
+#[derive(Debug, Deserialize)]
+pub struct ImageTagsRequest {
+    pub image_ids: Vec<i64>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Sets the star rating of several images of an album at once.
+#[derive(Debug, Deserialize)]
+pub struct ImageRatingRequest {
+    pub image_ids: Vec<i64>,
+    /// 1 to 5, or 0 to clear the rating
+    pub rating: i64,
+}
+
+/// Filters on images' EXIF data, tags and ratings. Every filter that is set has
+/// to match; the ranges include both ends.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageFilter {
+    /// Exact camera model, e.g. `X100V`
+    pub camera: Option<String>,
+    /// Exact lens model
+    pub lens: Option<String>,
+    /// f-numbers
+    pub aperture_min: Option<f64>,
+    pub aperture_max: Option<f64>,
+    pub iso_min: Option<i64>,
+    pub iso_max: Option<i64>,
+    /// Exposure times, in seconds
+    pub shutter_min: Option<f64>,
+    pub shutter_max: Option<f64>,
+    /// Focal lengths, in millimetres
+    pub focal_length_min: Option<f64>,
+    pub focal_length_max: Option<f64>,
+    /// Dates the photos were taken, as `YYYY-MM-DD`
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    /// Slugs of tags the images must all have
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Fewest stars
+    pub rating_min: Option<i64>,
+}
+
+/// An image matching an `ImageFilter`, with its EXIF data as stored and parsed
+/// into numbers.
+#[derive(Debug, Serialize)]
+pub struct FilteredImage {
+    pub id: i64,
+    pub album_id: i64,
+    pub album_name: String,
+    pub filename: String,
+    pub title: Option<String>,
+    pub camera_model: Option<String>,
+    pub lens_model: Option<String>,
+    pub iso: Option<String>,
+    pub aperture: Option<String>,
+    pub shutter_speed: Option<String>,
+    pub focal_length: Option<String>,
+    pub iso_value: Option<i64>,
+    pub aperture_value: Option<f64>,
+    pub shutter_seconds: Option<f64>,
+    pub focal_length_mm: Option<f64>,
+    /// `YYYY-MM-DD HH:MM:SS`
+    pub taken_at: Option<String>,
+}
+
+/// An album whose images are those in public albums matching its filter, rather
+/// than images uploaded to it, shown at `/smart-albums/<id>`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SmartAlbum {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub filter: ImageFilter,
+    pub created_at: String,
+}
+
+/// A smart album as listed on the home page, with its newest image as the cover.
+#[derive(Debug, Serialize)]
+pub struct SmartAlbumSummary {
+    #[serde(flatten)]
+    pub album: SmartAlbum,
+    pub num_images: usize,
+    pub cover_album_id: Option<i64>,
+    pub cover_image: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmartAlbumRequest {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub filter: ImageFilter,
+}
+
+/// A token that gives whoever holds it access to one album.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLink {
+    pub id: i64,
+    pub album_id: i64,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub allow_download: bool,
+    pub max_views: Option<i64>,
+    pub view_count: i64,
+    pub revoked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareLinkRequest {
+    /// Days until the link stops working, or never if unset
+    pub expires_in_days: Option<i64>,
+    #[serde(default)]
+    pub allow_download: bool,
+    /// How many times the link can be opened, or unlimited if unset
+    pub max_views: Option<i64>,
+}
@@ -1,3 +1,4 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use axum::extract::Multipart;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
@@ -5,18 +6,27 @@ use fast_image_resize::images::Image;
 use fast_image_resize::{PixelType, Resizer};
 use image::RgbImage;
 use rexif::ExifTag;
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
 use std::borrow::Cow;
 use std::error::Error;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
 use tokio::{fs, task};
 use uuid::Uuid;
 
+use crate::config::ImageConfig;
 use crate::db::create_image;
 use crate::handlers::admin::ProcessedImage;
-use crate::types::{AppState, CreateAlbumRequest};
+use crate::types::ImageDescription;
+use crate::xmp;
 
+/// Camera make, model, lens, ISO, aperture, shutter speed, focal length, light source and date taken.
+pub type ExifMetadata = (String, String, String, String, String, String, String, String, String);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ImageQuality {
     Full,
     Optimized,
@@ -71,7 +81,32 @@ pub async fn save_image(
     fs::write(path, file_data).await
 }
 
-pub fn extract_exif_metadata(data: &[u8]) -> Option<(String, String, String, String, String, String, String, String, String)> {
+/// Computes the hex-encoded SHA-256 of an uploaded file, used to detect duplicates.
+pub fn compute_checksum(data: &[u8]) -> String {
+    hex_encode(&Sha256::digest(data))
+}
+
+/// Hashes a random token, such as an API token, refresh token or recovery code, for
+/// storage and lookup.
+///
+/// Tokens are random enough that a plain SHA-256 is as good as a password hash here,
+/// and it lets them be looked up directly.
+pub fn hash_token(token: &str) -> String {
+    compute_checksum(token.as_bytes())
+}
+
+/// Generates 256 random bits, hex-encoded, for tokens and secrets.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex_encode(&bytes)
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn extract_exif_metadata(data: &[u8]) -> Option<ExifMetadata> {
     match rexif::parse_buffer(data) {
         Ok(exif) => {
             let mut camera_make = None;
@@ -115,12 +150,59 @@ pub fn extract_exif_metadata(data: &[u8]) -> Option<(String, String, String, Str
     }
 }
 
-pub async fn process_image(data: Vec<u8>) -> Result<ProcessedImage, Box<dyn Error + Send + Sync>> {
-    const OPTIMIZED_MAX_SIZE: u32 = 1920;
-    const THUMBNAIL_MAX_SIZE: u32 = 400;
-    const OPTIMIZED_QUALITY: i32 = 85;
-    const THUMBNAIL_QUALITY: i32 = 95;
+/// Reads an image's title, caption and keywords from its XMP metadata, falling back
+/// to the EXIF image description for the caption.
+pub fn extract_image_description(data: &[u8]) -> ImageDescription {
+    let packet = xmp::find_packet(data);
+    let first_value = |property| {
+        packet.and_then(|packet| xmp::property_values(packet, property).into_iter().next())
+    };
+
+    let title = first_value("dc:title");
+    let caption = first_value("dc:description").or_else(|| {
+        let exif = rexif::parse_buffer_quiet(data).0.ok()?;
+        exif.entries
+            .iter()
+            .find(|entry| entry.tag == ExifTag::ImageDescription)
+            .map(|entry| entry.value_more_readable.trim().to_string())
+            .filter(|description| !description.is_empty())
+    });
+
+    let mut keywords = Vec::new();
+    for keyword in packet
+        .map(|packet| xmp::property_values(packet, "dc:subject"))
+        .unwrap_or_default()
+    {
+        let keyword_lower = keyword.to_lowercase();
+        if !keywords
+            .iter()
+            .any(|k: &String| k.to_lowercase() == keyword_lower)
+        {
+            keywords.push(keyword);
+        }
+    }
+
+    ImageDescription {
+        title,
+        caption,
+        keywords,
+    }
+}
 
+/// The slug of a tag name: its words, lowercased and joined by hyphens. Empty if
+/// the name has no letters or digits.
+pub fn tag_slug(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub async fn process_image(
+    data: Vec<u8>,
+    config: ImageConfig,
+) -> Result<ProcessedImage, Box<dyn Error + Send + Sync>> {
     task::spawn_blocking(move || {
         // Decompress JPEG to RGB image
         let rgb_image: RgbImage = turbojpeg::decompress_image(&data)?;
@@ -133,15 +215,17 @@ pub async fn process_image(data: Vec<u8>) -> Result<ProcessedImage, Box<dyn Erro
         // Create resizer with default CPU optimizations
         let mut resizer = Resizer::new();
 
-        // Optimize to max OPTIMIZED_MAX_SIZE
-        let (opt_width, opt_height) = calculate_dimensions(width, height, OPTIMIZED_MAX_SIZE);
+        // Optimize to the configured max size
+        let (opt_width, opt_height) =
+            calculate_dimensions(width, height, config.optimized_max_size);
         let mut optimized_img = Image::new(opt_width, opt_height, PixelType::U8x3);
 
         // Resize the image
         resizer.resize(&src_image, &mut optimized_img, None)?;
 
         // Create low rez thumbnail
-        let (thumb_width, thumb_height) = calculate_dimensions(width, height, THUMBNAIL_MAX_SIZE);
+        let (thumb_width, thumb_height) =
+            calculate_dimensions(width, height, config.thumbnail_max_size);
         let mut thumbnail_img = Image::new(thumb_width, thumb_height, PixelType::U8x3);
 
         resizer.resize(&src_image, &mut thumbnail_img, None)?;
@@ -156,8 +240,16 @@ pub async fn process_image(data: Vec<u8>) -> Result<ProcessedImage, Box<dyn Erro
                 .ok_or("Failed to create thumbnail RGB image")?;
 
         // Compress using turbojpeg
-        let optimized = turbojpeg::compress_image(&optimized_rgb, OPTIMIZED_QUALITY, turbojpeg::Subsamp::Sub2x2)?;
-        let thumbnail = turbojpeg::compress_image(&thumbnail_rgb, THUMBNAIL_QUALITY, turbojpeg::Subsamp::Sub2x2)?;
+        let optimized = turbojpeg::compress_image(
+            &optimized_rgb,
+            config.optimized_quality,
+            turbojpeg::Subsamp::Sub2x2,
+        )?;
+        let thumbnail = turbojpeg::compress_image(
+            &thumbnail_rgb,
+            config.thumbnail_quality,
+            turbojpeg::Subsamp::Sub2x2,
+        )?;
 
         Ok(ProcessedImage {
             optimized: optimized.to_vec(),
@@ -194,18 +286,21 @@ pub async fn delete_album_directory(album_id: i64) -> io::Result<()> {
 }
 
 pub async fn process_and_save_images(
-    state: Arc<AppState>,
+    pool: &SqlitePool,
+    config: &ImageConfig,
     album_id: i64,
     images: Vec<(String, Vec<u8>)>,
 ) -> Result<usize, Box<dyn Error + Send + Sync>> {
     let mut tasks = Vec::new();
 
     for (original_filename, data) in images {
-        let state = state.clone();
-        let album_id = album_id;
+        let pool = pool.clone();
+        let config = *config;
         let filename = generate_unique_filename(&original_filename);
 
         tasks.push(tokio::spawn(async move {
+            let checksum = compute_checksum(&data);
+
             // Extract EXIF metadata
             let metadata = extract_exif_metadata(&data).unwrap_or((
                 "Unknown".to_string(),
@@ -219,11 +314,13 @@ pub async fn process_and_save_images(
                 "Unknown".to_string(),
             ));
 
+            let description = extract_image_description(&data);
+
             // Save full-resolution image
             save_image(&data, &filename, album_id, ImageQuality::Full).await?;
 
             // Process the image
-            let processed = process_image(data).await?;
+            let processed = process_image(data, config).await?;
 
             // Save optimized and thumbnail versions
             let save_optimized = save_image(
@@ -242,9 +339,11 @@ pub async fn process_and_save_images(
 
             // Create database entry
             create_image(
-                &state.pool,
+                &pool,
                 album_id,
                 &filename,
+                &original_filename,
+                &checksum,
                 processed.original_size as i64,
                 &metadata.0,
                 &metadata.1,
@@ -255,6 +354,7 @@ pub async fn process_and_save_images(
                 &metadata.6,
                 &metadata.7,
                 &metadata.8,
+                &description,
             )
             .await?;
 
@@ -263,20 +363,23 @@ pub async fn process_and_save_images(
     }
 
     let results = futures::future::join_all(tasks).await;
-    Ok(results.into_iter().filter(|r| r.is_ok()).count())
+    Ok(results
+        .into_iter()
+        .filter(|r| matches!(r, Ok(Ok(()))))
+        .count())
 }
 
 /// Extracts multipart fields from the stream.
 /// - `album_field`: the field name that contains the album JSON.
 /// - `image_field`: the field name that contains image file data.
 /// - `deleted_field`: optional field name for a comma‐separated list of deleted image IDs.
-pub async fn extract_multipart_fields(
+pub async fn extract_multipart_fields<T: DeserializeOwned>(
     mut multipart: Multipart,
     album_field: &str,
     image_field: &str,
     deleted_field: Option<&str>,
-) -> Result<(Option<CreateAlbumRequest>, Vec<(String, Vec<u8>)>, Vec<i64>), impl IntoResponse> {
-    let mut album_data: Option<CreateAlbumRequest> = None;
+) -> Result<(Option<T>, Vec<(String, Vec<u8>)>, Vec<i64>), impl IntoResponse> {
+    let mut album_data: Option<T> = None;
     let mut images: Vec<(String, Vec<u8>)> = Vec::new();
     let mut deleted_ids: Vec<i64> = Vec::new();
 
@@ -0,0 +1,204 @@
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::fs;
+use tokio::sync::mpsc;
+
+use crate::config::ImageConfig;
+use crate::import::{find_or_create_album, import_files, is_supported_image, list_image_files, ImportSummary};
+
+/// How long a file must go without changes before it is considered fully written.
+const SETTLE_DURATION: Duration = Duration::from_secs(3);
+
+/// How often pending files are checked for having settled.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A file seen in the inbox that hasn't been imported yet.
+struct PendingFile {
+    last_change: Instant,
+    last_size: Option<u64>,
+}
+
+/// Starts watching `inbox` for images dropped into `inbox/<album-name>/`.
+///
+/// Once a file has stopped changing it is imported into the album of the same
+/// name (created if needed) and the source is moved to `archive/<album-name>/`.
+/// Files that fail to import stay in the inbox, and are tried again when they
+/// change or the server restarts.
+pub fn spawn(pool: SqlitePool, image_config: ImageConfig, inbox: PathBuf, archive: PathBuf) {
+    tokio::spawn(async move {
+        if let Err(e) = run(pool, image_config, inbox, archive).await {
+            eprintln!("Inbox watcher stopped: {}", e);
+        }
+    });
+}
+
+async fn run(
+    pool: SqlitePool,
+    image_config: ImageConfig,
+    inbox: PathBuf,
+    archive: PathBuf,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(&inbox).await?;
+    fs::create_dir_all(&archive).await?;
+    let inbox = inbox.canonicalize()?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<PathBuf>();
+    let event_tx = tx.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        Ok(event) => {
+            if matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Access(_)
+            ) {
+                for path in event.paths {
+                    let _ = event_tx.send(path);
+                }
+            }
+        }
+        Err(e) => eprintln!("Inbox watch error: {}", e),
+    })?;
+    watcher.watch(&inbox, RecursiveMode::Recursive)?;
+    println!("Watching {} for new images", inbox.display());
+
+    // Pick up anything dropped in while the server wasn't running
+    let mut entries = fs::read_dir(&inbox).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            for path in list_image_files(&entry.path()).await? {
+                let _ = tx.send(path);
+            }
+        }
+    }
+
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            Some(path) = rx.recv() => {
+                if album_name_for(&inbox, &path).is_some() && is_supported_image(&path) {
+                    pending
+                        .entry(path)
+                        .and_modify(|p| p.last_change = Instant::now())
+                        .or_insert(PendingFile { last_change: Instant::now(), last_size: None });
+                }
+            }
+            _ = interval.tick() => {
+                let settled = take_settled(&mut pending).await;
+                if !settled.is_empty() {
+                    import_settled(&pool, &image_config, &inbox, &archive, settled).await;
+                }
+            }
+        }
+    }
+}
+
+/// Removes and returns the pending files whose size has stayed the same for
+/// at least `SETTLE_DURATION`.
+async fn take_settled(pending: &mut HashMap<PathBuf, PendingFile>) -> Vec<PathBuf> {
+    let mut settled = Vec::new();
+    let mut vanished = Vec::new();
+
+    for (path, file) in pending.iter_mut() {
+        let size = match fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => {
+                vanished.push(path.clone());
+                continue;
+            }
+        };
+
+        if file.last_size != Some(size) {
+            file.last_size = Some(size);
+            file.last_change = Instant::now();
+        } else if size > 0 && file.last_change.elapsed() >= SETTLE_DURATION {
+            settled.push(path.clone());
+        }
+    }
+
+    for path in vanished.iter().chain(settled.iter()) {
+        pending.remove(path);
+    }
+    settled
+}
+
+async fn import_settled(
+    pool: &SqlitePool,
+    image_config: &ImageConfig,
+    inbox: &Path,
+    archive: &Path,
+    files: Vec<PathBuf>,
+) {
+    let mut by_album: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in files {
+        if let Some(name) = album_name_for(inbox, &path) {
+            by_album.entry(name).or_default().push(path);
+        }
+    }
+
+    for (name, mut files) in by_album {
+        files.sort();
+
+        let album_id = match find_or_create_album(pool, &name, &files).await {
+            Ok((album_id, _)) => album_id,
+            Err(e) => {
+                eprintln!("Failed to create album '{}' from inbox: {}", name, e);
+                continue;
+            }
+        };
+
+        let mut summary = ImportSummary::default();
+        if let Err(e) = import_files(pool, image_config, album_id, &files, &mut summary).await {
+            eprintln!("Failed to import inbox files into '{}': {}", name, e);
+            continue;
+        }
+        println!(
+            "Inbox '{}': {} imported, {} skipped, {} failed",
+            name, summary.images_imported, summary.images_skipped, summary.images_failed
+        );
+
+        for path in files.iter().filter(|path| !summary.failed_files.contains(path)) {
+            if let Err(e) = archive_file(archive, &name, path).await {
+                eprintln!("Failed to archive {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Returns the album folder name for a file directly inside `inbox/<album-name>/`.
+fn album_name_for(inbox: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(inbox).ok()?;
+    let mut components = relative.components();
+    let album = components.next()?.as_os_str().to_str()?.to_string();
+    components.next()?;
+    if components.next().is_some() {
+        return None;
+    }
+    Some(album)
+}
+
+/// Moves an imported source file into `archive/<album-name>/`, never overwriting.
+async fn archive_file(archive: &Path, album_name: &str, path: &Path) -> std::io::Result<()> {
+    let dir = archive.join(album_name);
+    fs::create_dir_all(&dir).await?;
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut destination = dir.join(&file_name);
+    let mut counter = 1;
+    while fs::try_exists(&destination).await? {
+        let stem = Path::new(&file_name).file_stem().unwrap_or_default().to_string_lossy();
+        let extension = Path::new(&file_name).extension().unwrap_or_default().to_string_lossy();
+        destination = dir.join(format!("{}-{}.{}", stem, counter, extension));
+        counter += 1;
+    }
+
+    // Fall back to copy + delete when the archive is on another filesystem
+    if fs::rename(path, &destination).await.is_err() {
+        fs::copy(path, &destination).await?;
+        fs::remove_file(path).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+/// Finds the XMP packet embedded in an image file, where photo editors like
+/// Lightroom write the title, description and keywords.
+pub fn find_packet(data: &[u8]) -> Option<&str> {
+    const START: &[u8] = b"<x:xmpmeta";
+    const END: &[u8] = b"</x:xmpmeta>";
+
+    let start = find(data, START)?;
+    let end = start + find(&data[start..], END)? + END.len();
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+/// Returns the values of a property such as `dc:subject`, which holds its values
+/// as `rdf:li` items of an `rdf:Bag`, `rdf:Seq` or `rdf:Alt`.
+pub fn property_values(packet: &str, property: &str) -> Vec<String> {
+    let open = format!("<{}>", property);
+    let close = format!("</{}>", property);
+    let Some(start) = packet.find(&open) else {
+        return Vec::new();
+    };
+    let Some(end) = packet[start..].find(&close) else {
+        return Vec::new();
+    };
+
+    let mut values = Vec::new();
+    let mut rest = &packet[start..start + end];
+    while let Some(item) = rest.find("<rdf:li") {
+        rest = &rest[item..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        // Empty `<rdf:li/>` items have no closing tag, so carry on right after them
+        if rest[..tag_end].ends_with('/') {
+            rest = &rest[tag_end + 1..];
+            continue;
+        }
+        let Some(item_end) = rest.find("</rdf:li>") else {
+            break;
+        };
+        let value = unescape(rest[tag_end + 1..item_end].trim());
+        if !value.is_empty() {
+            values.push(value);
+        }
+        rest = &rest[item_end + "</rdf:li>".len()..];
+    }
+    values
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#xA;", "\n")
+        .replace("&amp;", "&")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}